@@ -3,6 +3,7 @@ pub(crate) static NOMENCLATURE_URL: &str = "http://hla.alleles.org/nomenclature/
 pub(crate) static MHCI_LIGAND_MOTIFS: &str = "A11, A3, Bw4-80T, Bw4-80I, Bw6, C1, C2, Unclassified";
 pub(crate) static EXPRESSION_CHANGES: &str = "N, L, S, C, A, Q and '' (blank)";
 pub(crate) static HLA_GENES: &str = "A, B, C, DP, DM, DO, DQ and DR";
+pub(crate) static AMBIGUITY_POLICIES: &str = "first, majority, common and ambiguous";
 
 use crate::ig_like::kir_ligand::IPD_KIR_URL;
 
@@ -48,6 +49,20 @@ pub enum NomenclatureError {
     #[error("Allele has insufficient information. A kir allele has to at least have a series specified (e.g. 003) but got '{0}'")]
     #[doc(hidden)]
     UnknownKirAllele(String),
+    #[error("Unknown KIR expression phenotype '{0}'. Accepted phenotypes are High, Low, Expressed and Null")]
+    #[doc(hidden)]
+    UnknownKirExpression(String),
+    #[error("Unknown KIR structural variant '{0}'. Accepted variants are KIR2DS4WT, KIR2DS4DEL, KIR3DL1ex4 and KIR3DL1ex9")]
+    #[doc(hidden)]
+    UnknownKirVariant(String),
+
+    /* KIR ligand related */
+    #[error(
+        "Unknown policy for ambiguous ligand motifs '{0}'. Accepted policies are {}",
+        AMBIGUITY_POLICIES
+    )]
+    #[doc(hidden)]
+    UnknownAmbiguityPolicy(String),
 }
 
 /// Errors related to parsing IPD website
@@ -91,4 +106,18 @@ pub enum IoError {
     #[error("Could not read line in Kir Ligand Info Table")]
     #[doc(hidden)]
     CouldNotParseLine,
+    #[error("Could not open Kir Ligand Info file")]
+    #[doc(hidden)]
+    CouldNotOpenFile(#[from] std::io::Error),
+    #[error("Could not read Kir Ligand Info from saved IPD-KIR HTML page:\n{0}")]
+    #[doc(hidden)]
+    CouldNotReadHtml(#[from] HtmlParseError),
+    #[error("Could not parse IPD JSON dump")]
+    #[doc(hidden)]
+    CouldNotParseJson(#[from] serde_json::Error),
+    #[error(
+        "IPD JSON dump has to be a list of alleles or an object with a 'data' list of alleles"
+    )]
+    #[doc(hidden)]
+    UnknownJsonLayout,
 }
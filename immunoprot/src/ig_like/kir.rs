@@ -5,10 +5,11 @@ use std::fmt::Formatter;
 use std::str::FromStr;
 
 use crate::error::NomenclatureError;
+use crate::mhc::hla::ExpressionChange;
 
-pub const KIR_TYPES: [&str; 13] = [
+pub const KIR_TYPES: [&str; 16] = [
     "KIR2DL1", "KIR2DL2", "KIR2DL3", "KIR2DL4", "KIR2DL5", "KIR2DS1", "KIR2DS2", "KIR2DS3",
-    "KIR2DS4", "KIR2DS5", "KIR3DS1", "KIR3DL1", "KIR3DL2",
+    "KIR2DS4", "KIR2DS5", "KIR3DS1", "KIR3DL1", "KIR3DL2", "KIR3DL3", "KIR2DP1", "KIR3DP1",
 ];
 
 #[derive(Debug, Eq, PartialEq, Clone, Hash)]
@@ -118,23 +119,26 @@ impl FromStr for KirProtein {
         }
     }
 }
+
+/// KIR allele following the IPD-KIR nomenclature, a three digit series (allotype) optionally
+/// followed by two digits for synonymous and two digits for non-coding differences
 #[derive(Debug, Eq, PartialEq, Clone, Hash)]
 pub struct KirAllele {
     series: String,
     cds_syn_sub: Option<String>,
     non_coding_sub: Option<String>,
+    expression_change: ExpressionChange,
 }
 
 impl std::fmt::Display for KirAllele {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "{}{}{}",
+            "{}{}{}{}",
             self.series,
-            self.cds_syn_sub.clone().unwrap_or_else(|| "".to_string()),
-            self.non_coding_sub
-                .clone()
-                .unwrap_or_else(|| "".to_string()),
+            self.cds_syn_sub.clone().unwrap_or_default(),
+            self.non_coding_sub.clone().unwrap_or_default(),
+            self.expression_change,
         )
     }
 }
@@ -143,26 +147,87 @@ impl FromStr for KirAllele {
     type Err = NomenclatureError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let series = s.chars().take(3).collect::<String>();
-        let cds_syn_sub = s.chars().skip(3).take(2).collect::<String>();
-        let non_coding_sub = s.chars().skip(5).take(2).collect::<String>();
+        let allele = s.trim().trim_start_matches('*');
+        let digits = allele
+            .chars()
+            .take_while(|c| c.is_ascii_digit())
+            .collect::<String>();
+        let expression_change = allele[digits.len()..].parse::<ExpressionChange>();
 
-        if series.len() != 3 {
-            Err(NomenclatureError::UnknownKirAllele(s.to_string()))
-        } else {
-            Ok(Self {
-                series,
-                cds_syn_sub: if cds_syn_sub.is_empty() {
-                    None
-                } else {
-                    Some(cds_syn_sub)
-                },
-                non_coding_sub: if non_coding_sub.is_empty() {
-                    None
-                } else {
-                    Some(non_coding_sub)
-                },
-            })
+        match (digits.len(), expression_change) {
+            (3, Ok(expression_change))
+            | (5, Ok(expression_change))
+            | (7, Ok(expression_change)) => Ok(Self {
+                series: digits[..3].to_string(),
+                cds_syn_sub: digits.get(3..5).map(String::from),
+                non_coding_sub: digits.get(5..7).map(String::from),
+                expression_change,
+            }),
+            _ => Err(NomenclatureError::UnknownKirAllele(s.to_string())),
+        }
+    }
+}
+
+impl KirAllele {
+    pub fn series(&self) -> &str {
+        &self.series
+    }
+
+    /// Whether the allele name itself marks it as not expressed (N suffix)
+    pub fn is_null(&self) -> bool {
+        self.expression_change == ExpressionChange::N
+    }
+
+    /// The allele reduced to its series, which determines the protein (allotype)
+    pub fn allotype(&self) -> KirAllele {
+        Self {
+            series: self.series.clone(),
+            cds_syn_sub: None,
+            non_coding_sub: None,
+            expression_change: ExpressionChange::Unknown,
+        }
+    }
+}
+
+/// Cell surface expression phenotype of a KIR allotype
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+pub enum KirExpression {
+    High,
+    Low,
+    Expressed,
+    Null,
+}
+
+impl KirExpression {
+    pub fn is_expressed(self) -> bool {
+        self != KirExpression::Null
+    }
+}
+
+impl std::fmt::Display for KirExpression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        use KirExpression::*;
+        let s = match self {
+            High => "High",
+            Low => "Low",
+            Expressed => "Expressed",
+            Null => "Null",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for KirExpression {
+    type Err = NomenclatureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use KirExpression::*;
+        match s.trim() {
+            "High" | "h" => Ok(High),
+            "Low" | "l" => Ok(Low),
+            "Expressed" => Ok(Expressed),
+            "Null" | "n" => Ok(Null),
+            s => Err(NomenclatureError::UnknownKirExpression(s.to_string())),
         }
     }
 }
@@ -198,7 +263,7 @@ impl FromStr for Kir {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let s = s.trim_start_matches("KIR");
-        let type_allele = s.splitn(2, |c| c == '*').collect::<Vec<&str>>();
+        let type_allele = s.splitn(2, '*').collect::<Vec<&str>>();
 
         let mut allele = None;
         let ig_like_domain = type_allele[0]
@@ -232,6 +297,33 @@ impl FromStr for Kir {
 }
 
 impl Kir {
+    /// The gene without any allele information
+    pub fn gene(&self) -> Kir {
+        Self {
+            allele: None,
+            ..self.clone()
+        }
+    }
+
+    /// The gene with the allele reduced to its allotype
+    pub fn allotype(&self) -> Kir {
+        Self {
+            allele: self.allele.as_ref().map(KirAllele::allotype),
+            ..self.clone()
+        }
+    }
+
+    pub fn allele(&self) -> Option<&KirAllele> {
+        self.allele.as_ref()
+    }
+
+    pub fn with_allele(&self, allele: KirAllele) -> Kir {
+        Self {
+            allele: Some(allele),
+            ..self.clone()
+        }
+    }
+
     pub fn is_activating(&self) -> bool {
         self.cytoplasmic_tail == Tail::Short
     }
@@ -239,13 +331,68 @@ impl Kir {
     pub fn is_inhibitory(&self) -> bool {
         self.cytoplasmic_tail == Tail::Long
     }
+
+    pub fn is_pseudogene(&self) -> bool {
+        self.cytoplasmic_tail == Tail::Pseudo
+    }
+}
+
+/// Common KIR structural variants typed in addition to gene presence, KIR2DS4 full-length (WT)
+/// versus the 22 bp deletion variant and KIR3DL1 exon 4 and exon 9 typing
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+pub enum KirVariant {
+    Kir2ds4FullLength,
+    Kir2ds4Deleted,
+    Kir3dl1Exon4,
+    Kir3dl1Exon9,
+}
+
+impl KirVariant {
+    /// The gene the variant was typed for
+    pub fn gene(self) -> Kir {
+        use KirVariant::*;
+        match self {
+            Kir2ds4FullLength | Kir2ds4Deleted => "KIR2DS4",
+            Kir3dl1Exon4 | Kir3dl1Exon9 => "KIR3DL1",
+        }
+        .parse::<Kir>()
+        .unwrap()
+    }
+}
+
+impl std::fmt::Display for KirVariant {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        use KirVariant::*;
+        let s = match self {
+            Kir2ds4FullLength => "KIR2DS4WT",
+            Kir2ds4Deleted => "KIR2DS4DEL",
+            Kir3dl1Exon4 => "KIR3DL1ex4",
+            Kir3dl1Exon9 => "KIR3DL1ex9",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for KirVariant {
+    type Err = NomenclatureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use KirVariant::*;
+        match s.trim_start_matches("KIR") {
+            "2DS4WT" => Ok(Kir2ds4FullLength),
+            "2DS4DEL" => Ok(Kir2ds4Deleted),
+            "3DL1ex4" => Ok(Kir3dl1Exon4),
+            "3DL1ex9" => Ok(Kir3dl1Exon9),
+            s => Err(NomenclatureError::UnknownKirVariant(s.to_string())),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::ig_like::kir::Domain;
     use crate::ig_like::kir::Tail::{self, *};
-    use crate::ig_like::kir::{Kir, KirProtein};
+    use crate::ig_like::kir::{Kir, KirAllele, KirProtein, KirVariant};
 
     #[test]
     fn test_kir_tail_naming_correct() {
@@ -270,4 +417,36 @@ mod tests {
         };
         assert_eq!(kir, expected);
     }
+
+    #[test]
+    fn test_parse_kir_allele() {
+        let kir = "KIR3DL1*0010101".parse::<Kir>().unwrap();
+        let allele = kir.allele().unwrap();
+
+        assert_eq!(allele.series(), "001");
+        assert_eq!(kir.to_string(), "KIR3DL1*0010101");
+        assert_eq!(kir.allotype().to_string(), "KIR3DL1*001");
+        assert_eq!(kir.gene(), "KIR3DL1".parse::<Kir>().unwrap());
+        assert_eq!("00402".parse::<KirAllele>().unwrap().series(), "004");
+        assert_eq!("*004N".parse::<KirAllele>().unwrap().to_string(), "004N");
+    }
+
+    #[test]
+    fn test_parse_kir_pseudogenes_and_variants() {
+        assert!("KIR3DP1".parse::<Kir>().unwrap().is_pseudogene());
+        assert!("KIR2DP1".parse::<Kir>().unwrap().is_pseudogene());
+        assert!("KIR3DL3".parse::<Kir>().unwrap().is_inhibitory());
+
+        let variant = "KIR2DS4DEL".parse::<KirVariant>().unwrap();
+        assert_eq!(variant, KirVariant::Kir2ds4Deleted);
+        assert_eq!(variant.gene(), "KIR2DS4".parse::<Kir>().unwrap());
+        assert_eq!(variant.to_string(), "KIR2DS4DEL");
+    }
+
+    #[test]
+    fn test_parse_kir_allele_incorrect() {
+        assert!("0010".parse::<KirAllele>().is_err());
+        assert!("01".parse::<KirAllele>().is_err());
+        assert!("001X".parse::<KirAllele>().is_err());
+    }
 }
@@ -14,6 +14,81 @@ pub const IPD_KIR_URL: &str = "https://www.ebi.ac.uk/cgi-bin/ipd/kir/retrieve_li
 pub const GENE_LOCI: [&str; 3] = ["A", "B", "C"];
 pub const SKIP_ROWS: usize = 1;
 
+/// Provides the raw IPD-KIR ligand HTML page for a gene locus or allele query
+pub trait IpdSource {
+    fn fetch(&self, query: &str) -> std::result::Result<String, HtmlParseError>;
+}
+
+/// Fetches IPD-KIR ligand pages over HTTP, the query is appended to the base URL
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct HttpIpdSource {
+    base_url: String,
+}
+
+impl HttpIpdSource {
+    pub fn new<T>(base_url: T) -> Self
+    where
+        T: AsRef<str>,
+    {
+        Self {
+            base_url: base_url.as_ref().to_string(),
+        }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+}
+
+impl Default for HttpIpdSource {
+    fn default() -> Self {
+        HttpIpdSource::new(IPD_KIR_URL)
+    }
+}
+
+impl IpdSource for HttpIpdSource {
+    fn fetch(&self, query: &str) -> std::result::Result<String, HtmlParseError> {
+        let url = format!("{}{}", self.base_url, query);
+        info!("Connecting to {}...", &url);
+        let response = attohttpc::get(&url).send()?;
+
+        response
+            .text()
+            .map_err(HtmlParseError::CouldNotReadResponse)
+    }
+}
+
+/// Formats of locally saved kir ligand tables that can be used for offline updates
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum LigandFileFormat {
+    /// Saved IPD-KIR ligand HTML page
+    Html,
+    /// Tab separated allele, motif and frequency columns
+    Tsv,
+    /// IPD JSON dump
+    Json,
+}
+
+impl LigandFileFormat {
+    /// Deduces the format from the file extension, defaulting to TSV
+    pub fn from_path<T>(p: T) -> Self
+    where
+        T: AsRef<std::path::Path>,
+    {
+        let extension = p
+            .as_ref()
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase());
+
+        match extension.as_deref() {
+            Some("html") | Some("htm") => LigandFileFormat::Html,
+            Some("json") => LigandFileFormat::Json,
+            _ => LigandFileFormat::Tsv,
+        }
+    }
+}
+
 #[derive(Debug, Eq, PartialEq, Hash, Clone, Ord, PartialOrd)]
 pub enum LigandMotif {
     A11,
@@ -142,10 +217,183 @@ impl FromStr for KirLigandInfo {
         }
     }
 }
+/// How a single ligand motif is chosen when an allele matches several alleles in the ligand table
+/// that do not share the same motif
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum AmbiguityPolicy {
+    /// First matching allele after sorting
+    First,
+    /// Motif shared by most matching alleles
+    Majority,
+    /// Majority motif among the matching alleles with a common frequency
+    PreferCommon,
+    /// No motif is assigned to ambiguous alleles
+    Ambiguous,
+}
+
+impl FromStr for AmbiguityPolicy {
+    type Err = NomenclatureError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        use AmbiguityPolicy::*;
+        match s.to_lowercase().as_str() {
+            "first" => Ok(First),
+            "majority" => Ok(Majority),
+            "common" | "prefer-common" => Ok(PreferCommon),
+            "ambiguous" => Ok(Ambiguous),
+            s => Err(NomenclatureError::UnknownAmbiguityPolicy(s.to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for AmbiguityPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use AmbiguityPolicy::*;
+        let s = match self {
+            First => "first",
+            Majority => "majority",
+            PreferCommon => "common",
+            Ambiguous => "ambiguous",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// A distinct motif among the alleles matched by a lookup, with the number of alleles carrying it
+/// and their frequency classes
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct MotifSupport {
+    pub motif: LigandMotif,
+    pub alleles: usize,
+    pub freqs: Vec<AlleleFreq>,
+}
+
+/// Ligand information an allele resolves to, alleles left unresolved by the `ambiguous` policy are
+/// told apart from alleles that are not in the table
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ResolvedLigand<'a> {
+    Info(&'a KirLigandInfo),
+    Ambiguous,
+    Missing,
+}
+
+impl<'a> ResolvedLigand<'a> {
+    /// Ligand information of the allele, there is none for ambiguous or missing alleles
+    pub fn info(self) -> Option<&'a KirLigandInfo> {
+        match self {
+            ResolvedLigand::Info(info) => Some(info),
+            _ => None,
+        }
+    }
+
+    pub fn is_ambiguous(self) -> bool {
+        self == ResolvedLigand::Ambiguous
+    }
+}
+
+/// All ligand table entries matching an allele, sorted
+#[derive(Debug, Clone)]
+pub struct LigandLookup<'a> {
+    matches: Vec<&'a KirLigandInfo>,
+}
+
+impl<'a> LigandLookup<'a> {
+    pub fn matches(&self) -> &[&'a KirLigandInfo] {
+        &self.matches
+    }
+
+    /// Distinct motifs of the matched alleles
+    pub fn motifs(&self) -> Vec<MotifSupport> {
+        motif_support(&self.matches)
+    }
+
+    /// Whether the matched alleles have more than one motif
+    pub fn is_ambiguous(&self) -> bool {
+        self.motifs().len() > 1
+    }
+
+    /// Chooses the ligand information representing the allele based on the policy
+    pub fn resolve(&self, policy: AmbiguityPolicy) -> ResolvedLigand<'a> {
+        use AmbiguityPolicy::*;
+
+        if policy == Ambiguous && self.is_ambiguous() {
+            return ResolvedLigand::Ambiguous;
+        }
+
+        let info = match policy {
+            First => self.matches.first().copied(),
+            Majority => majority_motif(&self.matches),
+            PreferCommon => {
+                let common = self
+                    .matches
+                    .iter()
+                    .filter(|info| *info.freq() == AlleleFreq::Common)
+                    .copied()
+                    .collect::<Vec<&KirLigandInfo>>();
+
+                if common.is_empty() {
+                    majority_motif(&self.matches)
+                } else {
+                    majority_motif(&common)
+                }
+            }
+            Ambiguous => self.matches.first().copied(),
+        };
+
+        info.map_or(ResolvedLigand::Missing, ResolvedLigand::Info)
+    }
+}
+
+fn motif_support(matches: &[&KirLigandInfo]) -> Vec<MotifSupport> {
+    let mut support = Vec::<MotifSupport>::new();
+
+    for info in matches {
+        match support.iter_mut().find(|s| s.motif == *info.motif()) {
+            Some(motif_support) => {
+                motif_support.alleles += 1;
+                if !motif_support.freqs.contains(info.freq()) {
+                    motif_support.freqs.push(info.freq().clone());
+                }
+            }
+            None => support.push(MotifSupport {
+                motif: info.motif().clone(),
+                alleles: 1,
+                freqs: vec![info.freq().clone()],
+            }),
+        }
+    }
+
+    support.iter_mut().for_each(|s| s.freqs.sort());
+    support.sort_by(|a, b| a.motif.cmp(&b.motif));
+    support
+}
+
+/// First of the (sorted) matches carrying the motif found in most matches, ties are broken by the
+/// motif order
+fn majority_motif<'a>(matches: &[&'a KirLigandInfo]) -> Option<&'a KirLigandInfo> {
+    let majority =
+        motif_support(matches)
+            .into_iter()
+            .fold(None, |best: Option<MotifSupport>, support| match best {
+                Some(best) if best.alleles >= support.alleles => Some(best),
+                _ => Some(support),
+            })?;
+
+    matches
+        .iter()
+        .find(|info| *info.motif() == majority.motif)
+        .copied()
+}
+
 #[derive(Debug, Eq, PartialEq)]
 pub struct KirLigandMap {
-    pub alleles: HashSet<ClassI>,
-    pub cache: HashMap<ClassI, KirLigandInfo>,
+    alleles: HashSet<ClassI>,
+    cache: HashMap<ClassI, KirLigandInfo>,
+    /// Index from the once and twice generalised form of an allele to the cached alleles it
+    /// covers, built on insertion so lookups of lower resolution alleles do not scan the table
+    generalised: HashMap<ClassI, Vec<ClassI>>,
+    /// Name of the table version the information was loaded from
+    version: Option<String>,
 }
 
 // NOTE: Could cause errors if the ligand map file is wrong
@@ -153,8 +401,14 @@ impl Default for KirLigandMap {
     fn default() -> Self {
         let alleles = HashSet::<ClassI>::new();
         let cache = HashMap::<ClassI, KirLigandInfo>::new();
+        let generalised = HashMap::<ClassI, Vec<ClassI>>::new();
 
-        Self { alleles, cache }
+        Self {
+            alleles,
+            cache,
+            generalised,
+            version: None,
+        }
     }
 }
 
@@ -167,10 +421,17 @@ impl KirLigandMap {
         KirLigandMap::from_loci(&GENE_LOCI)
     }
 
+    /// Same as `updated` but obtains the ligand pages from the provided source
+    pub fn updated_from<S>(source: &S) -> std::result::Result<Self, HtmlParseError>
+    where
+        S: IpdSource,
+    {
+        KirLigandMap::from_source(source, &GENE_LOCI)
+    }
+
     // TODO: Could be combined with parse but need to see about error specificity
     pub fn init() -> std::result::Result<Self, IoError> {
-        let mut alleles = HashSet::<ClassI>::new();
-        let mut cache = HashMap::<ClassI, KirLigandInfo>::new();
+        let mut map = KirLigandMap::new();
 
         for (row, line) in crate::LIGAND_MAP_DEF
             .lines()
@@ -181,11 +442,11 @@ impl KirLigandMap {
 
             let allele = entry[0]
                 .parse::<ClassI>()
-                .or_else(|_| Err(IoError::CouldNotReadAllele(row + 1)))?;
+                .map_err(|_| IoError::CouldNotReadAllele(row + 1))?;
 
             let motif = entry[1]
                 .parse::<LigandMotif>()
-                .or_else(|_| Err(IoError::CouldNotReadMotif(row + 1)))?;
+                .map_err(|_| IoError::CouldNotReadMotif(row + 1))?;
 
             let freq: AlleleFreq = if entry.len() == 3 {
                 entry[2].into()
@@ -193,33 +454,77 @@ impl KirLigandMap {
                 AlleleFreq::Unknown
             };
 
-            let info = KirLigandInfo::new(allele.clone(), motif, freq);
-
-            alleles.insert(allele.clone());
-            cache.insert(allele, info);
+            let info = KirLigandInfo::new(allele, motif, freq);
+            map.insert_info(info);
         }
 
-        Ok(Self { alleles, cache })
+        Ok(map)
+    }
+
+    /// Labels the map with the name of the table version it was loaded from
+    pub fn with_version<T>(mut self, version: T) -> Self
+    where
+        T: AsRef<str>,
+    {
+        self.version = Some(version.as_ref().to_string());
+        self
+    }
+
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    /// Alleles in the table
+    pub fn alleles(&self) -> &HashSet<ClassI> {
+        &self.alleles
+    }
+
+    /// Ligand information of exactly the allele, without the lookup of lower resolution alleles
+    pub fn info(&self, allele: &ClassI) -> Option<&KirLigandInfo> {
+        self.cache.get(allele)
+    }
+
+    /// Ligand information of every allele in the table
+    pub fn into_infos(self) -> impl Iterator<Item = KirLigandInfo> {
+        self.cache.into_values()
     }
 
     pub fn insert_info(&mut self, info: KirLigandInfo) {
-        self.alleles.insert(info.0.clone());
+        if self.alleles.insert(info.0.clone()) {
+            if let Some(generalised_once) = info.0.generalize() {
+                if let Some(generalised_twice) = generalised_once.generalize() {
+                    self.generalised
+                        .entry(generalised_twice)
+                        .or_default()
+                        .push(info.0.clone());
+                }
+                self.generalised
+                    .entry(generalised_once)
+                    .or_default()
+                    .push(info.0.clone());
+            }
+        }
         self.cache.insert(info.0.clone(), info);
     }
 
     pub fn from_loci(loci: &[&str]) -> std::result::Result<Self, HtmlParseError> {
-        let mut alleles = HashSet::<ClassI>::new();
-        let mut cache = HashMap::<ClassI, KirLigandInfo>::new();
+        KirLigandMap::from_source(&HttpIpdSource::default(), loci)
+    }
+
+    pub fn from_source<S>(source: &S, loci: &[&str]) -> std::result::Result<Self, HtmlParseError>
+    where
+        S: IpdSource,
+    {
+        let mut map = KirLigandMap::new();
 
         let results: std::result::Result<Vec<_>, HtmlParseError> = loci
             .iter()
             .map(|locus| {
-                let raw_html = get_ipd_html(locus)?;
+                let raw_html = get_ipd_html(source, locus)?;
                 let allele_infos = read_table(&raw_html, SKIP_ROWS)?;
 
                 for allele_info in allele_infos {
-                    alleles.insert(allele_info.0.clone());
-                    cache.insert(allele_info.0.clone(), allele_info);
+                    map.insert_info(allele_info);
                 }
 
                 Ok(())
@@ -228,7 +533,7 @@ impl KirLigandMap {
 
         results?;
 
-        Ok(Self { alleles, cache })
+        Ok(map)
     }
 
     pub fn from_path<T>(p: T) -> std::result::Result<Self, IoError>
@@ -248,11 +553,11 @@ impl KirLigandMap {
 
             let allele = entry[0]
                 .parse::<ClassI>()
-                .or_else(|_| Err(IoError::CouldNotReadAllele(row + 1)))?;
+                .map_err(|_| IoError::CouldNotReadAllele(row + 1))?;
 
             let motif = entry[1]
                 .parse::<LigandMotif>()
-                .or_else(|_| Err(IoError::CouldNotReadMotif(row + 1)))?;
+                .map_err(|_| IoError::CouldNotReadMotif(row + 1))?;
 
             let freq: AlleleFreq = if entry.len() == 3 {
                 entry[2].into()
@@ -267,40 +572,115 @@ impl KirLigandMap {
         Ok(map)
     }
 
-    pub fn get_allele_info(&self, allele: &ClassI) -> Vec<&KirLigandInfo> {
-        let mut kir_ligand_info = Vec::<&KirLigandInfo>::new();
+    /// Reads a locally saved kir ligand table in any of the supported formats
+    pub fn from_local_file<T>(p: T) -> std::result::Result<Self, IoError>
+    where
+        T: AsRef<std::path::Path>,
+    {
+        match LigandFileFormat::from_path(&p) {
+            LigandFileFormat::Html => KirLigandMap::from_html_path(p),
+            LigandFileFormat::Json => KirLigandMap::from_ipd_json(p),
+            LigandFileFormat::Tsv => KirLigandMap::from_path(p),
+        }
+    }
+
+    /// Reads the allele table from a saved IPD-KIR ligand HTML page
+    pub fn from_html_path<T>(p: T) -> std::result::Result<Self, IoError>
+    where
+        T: AsRef<std::path::Path>,
+    {
+        let mut map = KirLigandMap::new();
+        let raw_html = std::fs::read_to_string(p)?;
+
+        for info in read_table(&Html::parse_document(&raw_html), SKIP_ROWS)? {
+            map.insert_info(info);
+        }
+
+        Ok(map)
+    }
+
+    /// Reads an IPD JSON dump, either a list of allele entries or an object with a `data` list.
+    /// Entries need an allele (`name` or `allele`) and a motif (`ligand`, `motif` or `kir_ligand`),
+    /// frequency (`frequency` or `freq`) is optional
+    pub fn from_ipd_json<T>(p: T) -> std::result::Result<Self, IoError>
+    where
+        T: AsRef<std::path::Path>,
+    {
+        use serde_json::Value;
+
+        let get_field = |entry: &Value, fields: &[&str]| {
+            fields
+                .iter()
+                .filter_map(|field| entry.get(field).and_then(Value::as_str))
+                .next()
+                .map(String::from)
+        };
+
+        let mut map = KirLigandMap::new();
+        let dump: Value = serde_json::from_reader(std::fs::File::open(p)?)?;
+
+        let entries = match &dump {
+            Value::Array(entries) => entries,
+            Value::Object(dump) => match dump.get("data") {
+                Some(Value::Array(entries)) => entries,
+                _ => return Err(IoError::UnknownJsonLayout),
+            },
+            _ => return Err(IoError::UnknownJsonLayout),
+        };
+
+        for (row, entry) in entries.iter().enumerate() {
+            let allele = get_field(entry, &["name", "allele"])
+                .and_then(|allele| allele.parse::<ClassI>().ok())
+                .ok_or_else(|| IoError::CouldNotReadAllele(row + 1))?;
+
+            let motif = get_field(entry, &["ligand", "motif", "kir_ligand"])
+                .and_then(|motif| motif.parse::<LigandMotif>().ok())
+                .ok_or_else(|| IoError::CouldNotReadMotif(row + 1))?;
+
+            let freq: AlleleFreq = get_field(entry, &["frequency", "freq"])
+                .map(AlleleFreq::from)
+                .unwrap_or(AlleleFreq::Unknown);
+
+            map.insert_info(KirLigandInfo::new(allele, motif, freq));
+        }
 
+        Ok(map)
+    }
+
+    /// Same as `get_allele_info` but sorted and wrapped to allow resolving ambiguous motifs
+    pub fn lookup(&self, allele: &ClassI) -> LigandLookup<'_> {
+        let mut matches = self.get_allele_info(allele);
+        matches.sort();
+
+        LigandLookup { matches }
+    }
+
+    /// Returns the ligand information for the allele or, when the allele is not in the table, for
+    /// all alleles in the table that generalise to it
+    pub fn get_allele_info(&self, allele: &ClassI) -> Vec<&KirLigandInfo> {
         if let Some(allele_info) = self.cache.get(allele) {
-            kir_ligand_info.push(allele_info)
+            vec![allele_info]
         } else {
-            self.alleles.iter().for_each(|cached_allele| {
-                if let Some(generalised_once) = cached_allele.generalize() {
-                    if generalised_once == *allele {
-                        kir_ligand_info.push(self.cache.get(cached_allele).unwrap());
-                    } else if let Some(generalised_twice) = generalised_once.generalize() {
-                        if generalised_twice == *allele {
-                            kir_ligand_info.push(self.cache.get(cached_allele).unwrap());
-                        }
-                    }
-                }
-            });
+            self.generalised
+                .get(allele)
+                .map(|cached_alleles| {
+                    cached_alleles
+                        .iter()
+                        .filter_map(|cached_allele| self.cache.get(cached_allele))
+                        .collect()
+                })
+                .unwrap_or_default()
         }
-        kir_ligand_info
     }
 }
 
-/// Obtains raw HTL from the EBI website
-fn get_ipd_html<T>(gene_locus: T) -> std::result::Result<Html, HtmlParseError>
+/// Obtains raw HTML for the gene locus from the IPD source
+fn get_ipd_html<S, T>(source: &S, gene_locus: T) -> std::result::Result<Html, HtmlParseError>
 where
+    S: IpdSource,
     T: AsRef<str> + std::fmt::Display,
 {
-    let url = format!("{}{}", IPD_KIR_URL, &gene_locus);
-    info!("Connecting to {}...", &url);
-    let request = attohttpc::get(&url);
-    let response = request.send()?;
-    let text = response
-        .text()
-        .or_else(|err| Err(HtmlParseError::CouldNotReadResponse(err)))?;
+    let text = source.fetch(gene_locus.as_ref())?;
 
     info!(
         "Obtained response, looking for allele table for locus '{}'",
@@ -318,7 +698,7 @@ fn read_table(
     let mut result = Vec::<KirLigandInfo>::new();
 
     let selector =
-        Selector::parse("tr").or_else(|_| Err(HtmlParseError::CouldNotCreateParserForTable))?;
+        Selector::parse("tr").map_err(|_| HtmlParseError::CouldNotCreateParserForTable)?;
     info!("Found HLA allele table! Reading rows...");
 
     for row in html.select(&selector).skip(skip_rows) {
@@ -327,10 +707,10 @@ fn read_table(
         if table_row.len() == 3 || table_row.len() == 2 {
             let allele = table_row[0]
                 .parse::<ClassI>()
-                .or_else(|_| Err(HtmlParseError::CouldNotReadClassI(table_row[0].to_string())))?;
+                .map_err(|_| HtmlParseError::CouldNotReadClassI(table_row[0].to_string()))?;
             let motif = table_row[1]
                 .parse::<LigandMotif>()
-                .or_else(|_| Err(HtmlParseError::CouldNotReadClassI(table_row[1].to_string())))?;
+                .map_err(|_| HtmlParseError::CouldNotReadClassI(table_row[1].to_string()))?;
             let freq: AlleleFreq = if table_row.len() == 3 {
                 table_row[2].into()
             } else {
@@ -353,9 +733,60 @@ fn read_table(
 #[cfg(test)]
 mod tests {
     use crate::ig_like::kir_ligand::{
-        get_ipd_html, read_table, AlleleFreq, KirLigandInfo, KirLigandMap, LigandMotif,
+        get_ipd_html, read_table, AlleleFreq, HttpIpdSource, KirLigandInfo, KirLigandMap,
+        LigandFileFormat, LigandMotif,
     };
     use crate::mhc::hla::ClassI;
+    use std::io::{BufRead, BufReader, Write};
+
+    /// Local stand-in for the IPD-KIR server, serves saved pages from `tests/ipd` named after the
+    /// queried allele (e.g. `?C*01:02` is answered with `C01_02.html`)
+    fn stand_in_ipd() -> HttpIpdSource {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let mut rdr = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                rdr.read_line(&mut request_line).unwrap();
+
+                let mut header = String::new();
+                while rdr.read_line(&mut header).unwrap_or(0) > 2 {
+                    header.clear();
+                }
+
+                let query = request_line
+                    .split_whitespace()
+                    .nth(1)
+                    .and_then(|target| target.find('?').map(|i| &target[i + 1..]))
+                    .unwrap_or("")
+                    .replace("%2A", "*")
+                    .replace("%3A", ":");
+                let fixture = format!(
+                    "tests/ipd/{}.html",
+                    query.replace('*', "").replace(':', "_")
+                );
+
+                let (status, body) = match std::fs::read_to_string(fixture) {
+                    Ok(body) => ("200 OK", body),
+                    Err(_) => ("404 Not Found", String::new()),
+                };
+
+                let mut stream = stream;
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+
+        HttpIpdSource::new(format!("http://{}/?", addr))
+    }
 
     #[test]
     fn test_known_ligands() {
@@ -372,10 +803,9 @@ mod tests {
         });
     }
 
-    // TODO: NEED TO FIX IPD CONNECTION FOR THESE 3 TESTS
     #[test]
     fn test_connect_to_ipd() {
-        let html = get_ipd_html("C*01:02").unwrap();
+        let html = get_ipd_html(&stand_in_ipd(), "C*01:02").unwrap();
         let ligand_info = read_table(&html, 1).unwrap();
         let expected = KirLigandInfo::new(
             "C01:02:01:01".parse::<ClassI>().unwrap(),
@@ -389,7 +819,7 @@ mod tests {
     #[test]
     fn test_create_ligand_map() {
         let loci = ["A*02:07", "B*57:01", "C*01:02"];
-        let ligand_map = KirLigandMap::from_loci(&loci).unwrap();
+        let ligand_map = KirLigandMap::from_source(&stand_in_ipd(), &loci).unwrap();
 
         let mut motifs = Vec::<LigandMotif>::new();
         let mut expected: Vec<LigandMotif> = vec![
@@ -416,7 +846,7 @@ mod tests {
     #[test]
     fn test_query_ligand_map() {
         let loci = ["A*02:15", "A*02:16", "A*02:07"];
-        let ligand_map = KirLigandMap::from_loci(&loci).unwrap();
+        let ligand_map = KirLigandMap::from_source(&stand_in_ipd(), &loci).unwrap();
 
         let query_allele_missing = "A*02:15".parse::<ClassI>().unwrap();
         let query_allele_singly_matched = "A*02:16".parse::<ClassI>().unwrap();
@@ -452,4 +882,142 @@ mod tests {
         assert_eq!(*first_allele, expected_allele);
         assert_eq!(ligand_info, expected_ligand_info);
     }
+
+    #[test]
+    fn test_map_from_saved_html() {
+        let ligand_map = KirLigandMap::from_local_file("tests/ipd/C01_02.html").unwrap();
+        let allele = "C*01:02:02".parse::<ClassI>().unwrap();
+
+        assert_eq!(ligand_map.alleles.len(), 2);
+        assert_eq!(
+            ligand_map.cache.get(&allele),
+            Some(&KirLigandInfo::new(
+                allele.clone(),
+                LigandMotif::C1,
+                AlleleFreq::Rare
+            ))
+        );
+    }
+
+    #[test]
+    fn test_map_from_ipd_json() {
+        let ligand_map = KirLigandMap::from_local_file("tests/ipd/ligands.json").unwrap();
+        let allele = "C*01:02:01:01".parse::<ClassI>().unwrap();
+
+        assert_eq!(ligand_map.alleles.len(), 3);
+        assert_eq!(
+            ligand_map.cache.get(&allele),
+            Some(&KirLigandInfo::new(
+                allele.clone(),
+                LigandMotif::C1,
+                AlleleFreq::Unknown
+            ))
+        );
+    }
+
+    #[test]
+    fn test_ligand_file_format() {
+        assert_eq!(
+            LigandFileFormat::from_path("ligands.HTM"),
+            LigandFileFormat::Html
+        );
+        assert_eq!(
+            LigandFileFormat::from_path("ligands.json"),
+            LigandFileFormat::Json
+        );
+        assert_eq!(LigandFileFormat::from_path("lg.tsv"), LigandFileFormat::Tsv);
+    }
+
+    #[test]
+    fn test_indexed_lookup_matches_scan() {
+        let ligand_map = KirLigandMap::init().unwrap();
+        let queries = [
+            "A*02:07",
+            "A*02:07:01",
+            "B*57:01",
+            "C*01:02",
+            "A*02:15",
+            "C*07",
+        ];
+
+        for query in queries.iter() {
+            let query = query.parse::<ClassI>().unwrap();
+            let mut indexed = ligand_map.get_allele_info(&query);
+            let mut scanned = ligand_map
+                .alleles
+                .iter()
+                .filter(|allele| {
+                    let once = allele.generalize();
+                    let twice = once.as_ref().and_then(ClassI::generalize);
+                    once.as_ref() == Some(&query) || twice.as_ref() == Some(&query)
+                })
+                .filter_map(|allele| ligand_map.cache.get(allele))
+                .collect::<Vec<&KirLigandInfo>>();
+
+            indexed.sort();
+            scanned.sort();
+            assert_eq!(indexed, scanned);
+        }
+    }
+
+    #[test]
+    fn test_resolve_ambiguous_motifs() {
+        use crate::ig_like::kir_ligand::{AmbiguityPolicy, MotifSupport, ResolvedLigand};
+
+        let mut ligand_map = KirLigandMap::new();
+        vec![
+            ("B*15:01:01", "Unclassified", "Rare"),
+            ("B*15:01:02", "Bw4-80I", "Common or Well Defined"),
+            ("B*15:01:03", "Unclassified", "Rare"),
+        ]
+        .into_iter()
+        .for_each(|(allele, motif, freq)| {
+            ligand_map.insert_info(KirLigandInfo::new(
+                allele.parse().unwrap(),
+                motif.parse().unwrap(),
+                freq.into(),
+            ))
+        });
+
+        let lookup = ligand_map.lookup(&"B*15:01".parse::<ClassI>().unwrap());
+        let motif = |policy: AmbiguityPolicy| {
+            lookup
+                .resolve(policy)
+                .info()
+                .map(|info| info.motif().clone())
+        };
+
+        assert!(lookup.is_ambiguous());
+        assert_eq!(
+            lookup.motifs()[0],
+            MotifSupport {
+                motif: LigandMotif::Bw4_80I,
+                alleles: 1,
+                freqs: vec![AlleleFreq::Common]
+            }
+        );
+        assert_eq!(
+            motif(AmbiguityPolicy::First),
+            Some(LigandMotif::Unclassified)
+        );
+        assert_eq!(
+            motif(AmbiguityPolicy::Majority),
+            Some(LigandMotif::Unclassified)
+        );
+        assert_eq!(
+            motif(AmbiguityPolicy::PreferCommon),
+            Some(LigandMotif::Bw4_80I)
+        );
+        assert_eq!(motif(AmbiguityPolicy::Ambiguous), None);
+        assert_eq!(
+            lookup.resolve(AmbiguityPolicy::Ambiguous),
+            ResolvedLigand::Ambiguous
+        );
+        assert_eq!(
+            ligand_map
+                .lookup(&"B*58:01".parse::<ClassI>().unwrap())
+                .resolve(AmbiguityPolicy::Ambiguous),
+            ResolvedLigand::Missing
+        );
+    }
 }
@@ -237,6 +237,8 @@ impl std::str::FromStr for ClassI {
     type Err = NomenclatureError;
 
     // TODO: WARNING this might still not catch all possibilities
+    /// Parses alleles such as `HLA-A*01:01:38L`, a trailing expression change suffix is removed from
+    /// the last field (or the allele group) and kept as the expression change
     fn from_str(s: &str) -> Result<Self> {
         if s.is_empty() {
             return Err(EmptyAlleleString);
@@ -249,7 +251,7 @@ impl std::str::FromStr for ClassI {
         } else {
             let mut hla_parts = hla.split(':');
 
-            let (gene, allele_group) = hla_parts
+            let (gene, mut allele_group) = hla_parts
                 .next()
                 .map(|required_fields| {
                     let gene = required_fields[0..1].chars().collect::<Gene>();
@@ -259,18 +261,31 @@ impl std::str::FromStr for ClassI {
                 })
                 .ok_or_else(|| NomenclatureError::GeneUnknown(s.to_string()))?;
 
+            // The gene letter alone is never an expression change
             let expression_change = hla
                 .chars()
+                .skip(1)
                 .last()
                 .map(ExpressionChange::from)
                 .unwrap_or_else(|| ExpressionChange::Unknown);
 
+            // The expression change is kept separately from the last field so it is not repeated
+            // when displayed
+            let mut fields = hla_parts.map(String::from).collect::<Vec<String>>();
+            if expression_change != ExpressionChange::Unknown {
+                match fields.last_mut() {
+                    Some(last_field) => last_field.pop(),
+                    _ => allele_group.pop(),
+                };
+            }
+            let mut fields = fields.into_iter();
+
             Ok(Self {
                 gene,
                 allele_group,
-                hla_protein: hla_parts.next().map(String::from),
-                cds_syn_sub: hla_parts.next().map(String::from),
-                non_coding: hla_parts.next().map(String::from),
+                hla_protein: fields.next(),
+                cds_syn_sub: fields.next(),
+                non_coding: fields.next(),
                 expression_change,
                 ligand_info: None,
             })
@@ -287,15 +302,15 @@ impl std::fmt::Display for ClassI {
             self.hla_protein
                 .clone()
                 .map(|protein| format!(":{}", protein))
-                .unwrap_or_else(|| "".to_string()),
+                .unwrap_or_default(),
             self.cds_syn_sub
                 .clone()
                 .map(|synonymous| format!(":{}", synonymous))
-                .unwrap_or_else(|| "".to_string()),
+                .unwrap_or_default(),
             self.non_coding
                 .clone()
                 .map(|non_coding| format!(":{}", non_coding))
-                .unwrap_or_else(|| "".to_string()),
+                .unwrap_or_default(),
             self.expression_change
         );
 
@@ -305,7 +320,7 @@ impl std::fmt::Display for ClassI {
 
 impl ClassI {
     pub fn to_nomenclature_string(&self) -> String {
-        format!("HLA-{}", self.to_string())
+        format!("HLA-{}", self)
     }
 }
 
@@ -370,4 +385,32 @@ mod tests {
 
         assert_eq!(expected, generalized.unwrap());
     }
+
+    #[test]
+    fn test_expression_change_round_trip() {
+        let allele = "A*01:01:38L".parse::<ClassI>().unwrap();
+
+        assert_eq!(allele.expression_change, ExpressionChange::L);
+        assert_eq!(allele.to_string(), "A*01:01:38L");
+        assert_eq!(allele.to_string().parse::<ClassI>().unwrap(), allele);
+    }
+
+    #[test]
+    fn test_expression_change_fields() {
+        let four_field = "HLA-B*44:02:01:02S".parse::<ClassI>().unwrap();
+        assert_eq!(four_field.non_coding, Some("02".to_string()));
+        assert_eq!(four_field.expression_change, ExpressionChange::S);
+        assert_eq!(four_field.to_string(), "B*44:02:01:02S");
+
+        let two_field = "C*04:09N".parse::<ClassI>().unwrap();
+        assert_eq!(two_field.hla_protein, Some("09".to_string()));
+        assert_eq!(two_field.expression_change, ExpressionChange::N);
+
+        let expressed = "B*27:05".parse::<ClassI>().unwrap();
+        assert_eq!(expressed.expression_change, ExpressionChange::Unknown);
+        assert_eq!(expressed.to_string(), "B*27:05");
+
+        let gene = "C".parse::<ClassI>().unwrap();
+        assert_eq!(gene.expression_change, ExpressionChange::Unknown);
+    }
 }
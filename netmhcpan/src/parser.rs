@@ -15,8 +15,8 @@ use immunoprot::mhc::hla::ClassI;
 
 /* Basic Parsers */
 pub fn take_first_numeric(i: &str) -> IResult<&str, &str> {
-    let take_until_digit = take_while(|c: char| !c.is_digit(10));
-    let take_digits = take_while1(|c: char| c.is_digit(10) || c == '.');
+    let take_until_digit = take_while(|c: char| !c.is_ascii_digit());
+    let take_digits = take_while1(|c: char| c.is_ascii_digit() || c == '.');
 
     let (remainder, (_, numeric)) = tuple((take_until_digit, take_digits))(i)?;
 
@@ -35,7 +35,7 @@ pub fn take_word(i: &str) -> IResult<&str, &str> {
 pub fn take_hla_allele(i: &str) -> IResult<&str, ClassI> {
     let allele_prefix = opt(tag("HLA-"));
     let take_allele = take_while(|c: char| {
-        c.is_digit(10) || HLA_GENES.contains(&c) || HLA_GENE_SEPARATORS.contains(&c)
+        c.is_ascii_digit() || HLA_GENES.contains(&c) || HLA_GENE_SEPARATORS.contains(&c)
     });
 
     let (remainder, (_, hla)) = tuple((allele_prefix, take_allele))(i)?;
@@ -61,7 +61,7 @@ pub fn is_version_line(i: &str) -> IResult<&str, Option<&str>> {
 pub fn is_peptide_line(i: &str) -> IResult<&str, bool> {
     let (non_space, _) = take_while(|c: char| c.is_whitespace())(i)?;
     match non_space.chars().next() {
-        Some(c) if c.is_digit(10) => Ok((i, true)),
+        Some(c) if c.is_ascii_digit() => Ok((i, true)),
         _ => Ok((i, false)),
     }
 }
@@ -190,12 +190,11 @@ mod tests {
     fn test_pep_line() {
         let netmhcout = std::fs::read_to_string("tests/netmhcpan_woBA.txt").unwrap();
 
-        netmhcout
-            .lines()
-            .for_each(|line| match is_peptide_line(line) {
-                Ok((pep_line, true)) => println!("{}", pep_line),
-                _ => (),
-            });
+        netmhcout.lines().for_each(|line| {
+            if let Ok((pep_line, true)) = is_peptide_line(line) {
+                println!("{}", pep_line)
+            }
+        });
     }
     #[test]
     fn test_identify_nn_line() {
@@ -205,12 +204,9 @@ mod tests {
             netmhcout
                 .lines()
                 .fold(Vec::<NearestNeighbour>::new(), |mut nn_neighbours, line| {
-                    match is_nn_line(line).unwrap() {
-                        (nn_line, Some(_)) => {
-                            let (_, nn) = get_nn_info(nn_line).unwrap();
-                            nn_neighbours.push(nn)
-                        }
-                        _ => (),
+                    if let (nn_line, Some(_)) = is_nn_line(line).unwrap() {
+                        let (_, nn) = get_nn_info(nn_line).unwrap();
+                        nn_neighbours.push(nn)
                     }
                     nn_neighbours
                 });
@@ -231,12 +227,9 @@ mod tests {
             netmhcout
                 .lines()
                 .fold(Vec::<RankThreshold>::new(), |mut thresholds, line| {
-                    match is_rank_line(line).unwrap() {
-                        (rank_line, Some(_)) => {
-                            let (_, rank) = get_rank_info(rank_line).unwrap();
-                            thresholds.push(rank)
-                        }
-                        _ => (),
+                    if let (rank_line, Some(_)) = is_rank_line(line).unwrap() {
+                        let (_, rank) = get_rank_info(rank_line).unwrap();
+                        thresholds.push(rank)
                     }
                     thresholds
                 });
@@ -248,9 +241,9 @@ mod tests {
         let (i, entry_info) = get_netmhc_entry_info(TEST_ENTRY).unwrap();
         let (i, alignment_info) = get_netmhc_align_info(i).unwrap();
 
-        let mut protein = Protein::new(alignment_info.2.clone());
+        let mut protein = Protein::new(alignment_info.2);
         protein
-            .add_sequence_at_pos(entry_info.0, &entry_info.2)
+            .add_sequence_at_pos(entry_info.0, entry_info.2)
             .unwrap();
         let peptide = Peptide::new(
             entry_info.0,
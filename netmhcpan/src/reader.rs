@@ -81,10 +81,8 @@ where
                         let protein = binding_data
                             .proteome
                             .entry(identity.to_string())
-                            .or_insert_with(|| Protein::new(identity.to_string()));
-                        protein
-                            .add_sequence_at_pos(pos, pep_seq.to_string())
-                            .unwrap();
+                            .or_insert_with(|| Protein::new(identity));
+                        protein.add_sequence_at_pos(pos, pep_seq).unwrap();
 
                         let peptide = Peptide::new(
                             pos,
@@ -124,7 +122,7 @@ mod tests {
         let bd = read_raw_netmhcpan(vec!["tests/netmhcpan_wBA.txt"]).unwrap();
         let allele = "B27:05".parse().unwrap();
 
-        let motif_pos = vec![2usize, 7usize, 8usize, 9usize]
+        let motif_pos = [2usize, 7usize, 8usize, 9usize]
             .iter()
             .map(|i| i - 1)
             .collect::<Vec<usize>>();
@@ -124,15 +124,15 @@ impl Peptide {
     /// Converts a peptide sequence to core representation.  Undefined behaviour with cases where there
     /// are both gaps and insertions in the alignment
     pub fn core(&self) -> String {
-        if self.gap_region().len() != 0 {
+        if !self.gap_region().is_empty() {
             self.seq()
                 .chars()
                 .enumerate()
                 .filter(|(i, _)| !self.gap.contains(i))
                 .map(|(_, c)| c)
                 .collect()
-        } else if self.ins.len() != 0 {
-            self.seq().chars().enumerate().map(|(_, c)| c).collect()
+        } else if !self.ins.is_empty() {
+            self.seq().chars().collect()
         } else {
             self.seq().to_string()
         }
@@ -276,9 +276,14 @@ impl BindingInfo {
     pub fn rank(&self) -> f32 {
         self.rank
     }
+
+    /// Predicted affinity in nM, only present in predictions run with binding affinity
+    pub fn affinity(&self) -> Option<f32> {
+        self.affinity
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct BindingData {
     pub(crate) alleles: HashSet<NearestNeighbour>,
     pub(crate) allele_binding: HashMap<ClassI, Vec<BindingInfo>>,
@@ -288,19 +293,6 @@ pub struct BindingData {
     pub(crate) strong_threshold: Option<f32>,
 }
 
-impl Default for BindingData {
-    fn default() -> Self {
-        Self {
-            alleles: HashSet::<NearestNeighbour>::new(),
-            allele_binding: HashMap::<ClassI, Vec<BindingInfo>>::new(),
-            proteome: HashMap::<String, Protein>::new(),
-            peptides: HashSet::<Peptide>::new(),
-            weak_threshold: None,
-            strong_threshold: None,
-        }
-    }
-}
-
 impl BindingData {
     pub fn new() -> Self {
         BindingData::default()
@@ -329,6 +321,10 @@ impl BindingData {
             .collect::<Vec<String>>()
     }
 
+    pub fn protein(&self, identity: &str) -> Option<&Protein> {
+        self.proteome.get(identity)
+    }
+
     pub fn pep_lengths(&self) -> Vec<usize> {
         let mut pep_lengths = self
             .peptides
@@ -1,10 +1,14 @@
 use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::mpsc::SyncSender;
 
-use crate::cohort::Individual;
+use crate::cohort::{HaplotypePair, Individual};
 use crate::error::Error;
+use crate::receptor::{HlaLigand, KirReceptor};
 
-use immunoprot::ig_like::kir::Kir;
-use immunoprot::ig_like::kir_ligand::{KirLigandMap, LigandMotif};
+use immunoprot::error::NomenclatureError;
+use immunoprot::ig_like::kir::{Kir, KirExpression};
+use immunoprot::ig_like::kir_ligand::{AmbiguityPolicy, KirLigandMap, LigandMotif};
 use immunoprot::mhc::hla::ClassI;
 use netmhcpan::result::{BindingData, BindingInfo};
 
@@ -16,11 +20,12 @@ use serde::{Deserialize, Serialize};
 
 /// Represents the motif positions to be used for calculating fraction of shared peptides.
 /// Might be extended by a field representing whether the calculations should take KIR genotypes into
-/// consideration.
+/// consideration. When `engaging` is set only peptides engaging the receptor are considered.
 #[derive(Debug, PartialEq, Eq, Hash)]
 pub struct Measure {
     pub name: String,
     pub motif_pos: Vec<usize>,
+    pub engaging: Option<KirEngagement>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -41,136 +46,867 @@ pub struct CalcFsResult {
     )]
     pub non_index_ligand_motif: Option<LigandMotif>,
     pub fraction_shared: f32,
+    #[serde(serialize_with = "crate::io::ser::optional_value_serialize")]
+    pub non_index_fraction_shared: Option<f32>,
+    #[serde(serialize_with = "crate::io::ser::optional_value_serialize")]
+    pub jaccard: Option<f32>,
+    #[serde(serialize_with = "crate::io::ser::optional_value_serialize")]
+    pub dice: Option<f32>,
+    #[serde(serialize_with = "crate::io::ser::optional_value_serialize")]
+    pub overlap_coefficient: Option<f32>,
+    #[serde(serialize_with = "crate::io::ser::optional_value_serialize")]
+    pub shared_motifs: Option<f32>,
+    /// Bootstrap percentile confidence interval and standard error of the fraction shared
+    #[serde(serialize_with = "crate::io::ser::optional_value_serialize")]
+    pub fs_ci_lower: Option<f32>,
+    #[serde(serialize_with = "crate::io::ser::optional_value_serialize")]
+    pub fs_ci_upper: Option<f32>,
+    #[serde(serialize_with = "crate::io::ser::optional_value_serialize")]
+    pub fs_se: Option<f32>,
+    /// Mean FS of the null model's random peptides and the empirical p-value of the fraction shared
+    #[serde(serialize_with = "crate::io::ser::optional_value_serialize")]
+    pub expected_fs: Option<f32>,
+    #[serde(serialize_with = "crate::io::ser::optional_value_serialize")]
+    pub fs_p_value: Option<f32>,
     pub peptide_length: usize,
+    /// Binding cutoffs of the index and non index motifs
+    #[serde(with = "serde_with::rust::display_fromstr")]
+    pub threshold: BindingCutoff,
+    #[serde(with = "serde_with::rust::display_fromstr")]
+    pub non_index_threshold: BindingCutoff,
     pub index_bound: usize,
     pub non_index_bound: usize,
+    pub ligand_table: String,
+    /// Weights of the non index motifs and of those shared with the index allele (motif counts for
+    /// hard binding), the non index fraction shared is derived from them
+    #[serde(skip)]
+    pub non_index_weight: f32,
+    #[serde(skip)]
+    pub non_index_shared: f32,
+    /// The same weights over the distinct motifs, used by the symmetric overlap metrics
+    #[serde(skip)]
+    pub unique_index_weight: f32,
+    #[serde(skip)]
+    pub unique_non_index_weight: f32,
+    #[serde(skip)]
+    pub unique_shared: f32,
 }
 
-#[derive(Debug)]
-pub struct CalculatorComb<'a> {
-    pub alleles: (&'a ClassI, &'a ClassI),
-    pub binding_data: (&'a [BindingInfo], &'a [BindingInfo]),
+impl CalcFsResult {
+    /// Fills in the selected overlap metrics, metrics of empty motif sets are NaN like the fraction
+    /// shared. The symmetric metrics are calculated on the distinct motifs of both alleles so
+    /// duplicated motifs can not push them above 1, for hard binding they count distinct motifs.
+    pub fn with_metrics(mut self, metrics: &[OverlapMetric]) -> Self {
+        let (index, non_index) = (self.unique_index_weight, self.unique_non_index_weight);
+        let shared = self.unique_shared;
+
+        metrics.iter().for_each(|metric| match metric {
+            OverlapMetric::NonIndexFraction => {
+                self.non_index_fraction_shared = Some(self.non_index_shared / self.non_index_weight)
+            }
+            OverlapMetric::Jaccard => self.jaccard = Some(shared / (index + non_index - shared)),
+            OverlapMetric::Dice => self.dice = Some(2.0 * shared / (index + non_index)),
+            OverlapMetric::Overlap => {
+                self.overlap_coefficient = Some(shared / index.min(non_index))
+            }
+            OverlapMetric::Shared => self.shared_motifs = Some(shared),
+        });
+
+        self
+    }
 }
 
-impl<'a> CalculatorComb<'a> {
-    pub fn new(
-        index_allele: &'a ClassI,
-        non_index_allele: &'a ClassI,
-        index_bd: &'a [BindingInfo],
-        non_index_bd: &'a [BindingInfo],
-    ) -> Self {
-        Self {
-            alleles: (index_allele, non_index_allele),
-            binding_data: (index_bd, non_index_bd),
+/// Overlap metrics reported next to the fraction of index motifs shared with the non index allele
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum OverlapMetric {
+    /// Fraction of the non index motifs shared with the index allele
+    NonIndexFraction,
+    Jaccard,
+    /// Sørensen–Dice coefficient
+    Dice,
+    /// Shared motifs relative to the smaller of the two motif sets
+    Overlap,
+    /// Number of shared motifs
+    Shared,
+}
+
+impl FromStr for OverlapMetric {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "non-index-fraction" => Ok(OverlapMetric::NonIndexFraction),
+            "jaccard" => Ok(OverlapMetric::Jaccard),
+            "dice" => Ok(OverlapMetric::Dice),
+            "overlap" => Ok(OverlapMetric::Overlap),
+            "shared" => Ok(OverlapMetric::Shared),
+            s => Err(Error::UnknownOverlapMetric(s.to_string())),
         }
     }
+}
+
+impl std::fmt::Display for OverlapMetric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            OverlapMetric::NonIndexFraction => "non-index-fraction",
+            OverlapMetric::Jaccard => "jaccard",
+            OverlapMetric::Dice => "dice",
+            OverlapMetric::Overlap => "overlap",
+            OverlapMetric::Shared => "shared",
+        };
+
+        write!(f, "{}", s)
+    }
+}
 
-    pub fn get_motifs(
-        &self,
-        threshold: f32,
-        length: usize,
-        aa_pos: &[usize],
-    ) -> (Vec<String>, Vec<String>) {
-        let bound_motifs = |item: &BindingInfo| {
-            if item.rank() < threshold && item.len() == length {
-                Some(item.motif(aa_pos))
-            } else {
-                None
+/// Settings of the pairwise FS calculations
+#[derive(Debug, Clone, Default)]
+pub struct FsOptions<'a> {
+    pub pep_lengths: Vec<usize>,
+    pub bootstrap: Option<Bootstrap>,
+    pub null_model: Option<NullModel<'a>>,
+}
+
+/// FNV-1a hash of the fields, unlike the standard library's hasher the hash is specified so seeds
+/// and motif orders are the same on every platform and release
+fn stable_hash(fields: &[&[u8]]) -> u64 {
+    use std::hash::Hasher;
+
+    let mut hasher = fnv::FnvHasher::default();
+    fields.iter().for_each(|field| {
+        hasher.write(field);
+        // Separates the fields so e.g. ("ab", "c") and ("a", "bc") differ
+        hasher.write(&[0xff]);
+    });
+    hasher.finish()
+}
+
+/// Seed of the random numbers of a single calculation, derived from the run's seed and the fields
+/// and peptide length identifying the calculation
+fn calculation_seed(seed: u64, fields: &[&str], length: usize) -> u64 {
+    let seed = seed.to_le_bytes();
+    let length = (length as u64).to_le_bytes();
+    let fields = std::iter::once(&seed[..])
+        .chain(fields.iter().map(|field| field.as_bytes()))
+        .chain(std::iter::once(&length[..]))
+        .collect::<Vec<&[u8]>>();
+
+    stable_hash(&fields)
+}
+
+/// Bootstrap resampling of the index allele's bound motifs, replicates are seeded from the seed
+/// and the allele pair so results do not depend on the order they are calculated in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bootstrap {
+    pub replicates: usize,
+    pub seed: u64,
+    /// Coverage of the percentile confidence intervals (e.g. 0.95)
+    pub level: f32,
+}
+
+/// Percentile confidence interval and standard error of a bootstrapped FS
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FsInterval {
+    pub lower: f32,
+    pub upper: f32,
+    pub se: f32,
+}
+
+impl Bootstrap {
+    pub fn new(replicates: usize, seed: u64, level: f32) -> Result<Self, Error> {
+        if replicates < 2 || !(level > 0.0 && level < 1.0) {
+            return Err(Error::InvalidBootstrap(replicates, level));
+        }
+
+        Ok(Self {
+            replicates,
+            seed,
+            level,
+        })
+    }
+
+    /// Seed of the replicates of a single allele pair, measure and peptide length
+    fn pair_seed(&self, index: &ClassI, non_index: &ClassI, measure: &str, length: usize) -> u64 {
+        calculation_seed(
+            self.seed,
+            &[&index.to_string(), &non_index.to_string(), measure],
+            length,
+        )
+    }
+
+    /// Bootstrapped FS from the weight and shared weight of every index motif, replicates are
+    /// calculated in parallel. There is no interval without any index motifs.
+    pub fn fraction_shared(&self, shared: &[(f32, f32)], pair_seed: u64) -> Option<FsInterval> {
+        use rand::{Rng, SeedableRng};
+        use rand_chacha::ChaCha8Rng;
+
+        if shared.is_empty() {
+            return None;
+        }
+
+        let mut replicates = (0..self.replicates as u64)
+            .into_par_iter()
+            .map(|replicate| {
+                let mut rng = ChaCha8Rng::seed_from_u64(pair_seed.wrapping_add(replicate));
+                let (weight, shared_weight) =
+                    (0..shared.len()).fold((0.0, 0.0), |(weight, shared_weight), _| {
+                        let (motif_weight, motif_shared) = shared[rng.gen_range(0, shared.len())];
+                        (weight + motif_weight, shared_weight + motif_shared)
+                    });
+                shared_weight / weight
+            })
+            .collect::<Vec<f32>>();
+        replicates.sort_by(|a, b| a.partial_cmp(b).expect("Tried to compare a NaN"));
+
+        let n = replicates.len() as f32;
+        let mean = replicates.iter().sum::<f32>() / n;
+        let variance = replicates.iter().map(|fs| (fs - mean).powi(2)).sum::<f32>() / (n - 1.0);
+        let percentile = |p: f32| replicates[((n - 1.0) * p).round() as usize];
+        let tail = (1.0 - self.level) / 2.0;
+
+        Some(FsInterval {
+            lower: percentile(tail),
+            upper: percentile(1.0 - tail),
+            se: variance.sqrt(),
+        })
+    }
+}
+
+/// Shuffles after which an engaging measure's peptide none of whose shuffles engage the receptor
+/// is left without a null peptide
+const MAX_SHUFFLES: usize = 100;
+
+/// Permutation null model of the FS, each replicate replaces the index allele's bound peptides by a
+/// random permutation of their residues, keeping the length and amino acid composition, and scores
+/// their motifs against the non index allele. The shuffled peptides are not scored for binding but
+/// keep the binding weight of the peptide they replace. Replicates are seeded from the seed and the
+/// index allele so every non index allele is compared to the same shuffled peptides, which are
+/// drawn once per index allele.
+#[derive(Debug, Clone)]
+pub struct NullModel<'a> {
+    pub replicates: usize,
+    pub seed: u64,
+    binding_data: &'a BindingData,
+}
+
+/// Expected FS of the null model and the empirical p-value of the observed FS
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NullFs {
+    pub expected: f32,
+    pub p_value: f32,
+}
+
+impl<'a> NullModel<'a> {
+    pub fn new(replicates: usize, seed: u64, binding_data: &'a BindingData) -> Result<Self, Error> {
+        if replicates == 0 {
+            return Err(Error::InvalidNullReplicates(replicates));
+        }
+
+        Ok(Self {
+            replicates,
+            seed,
+            binding_data,
+        })
+    }
+
+    /// Random permutation of the peptide's residues, engaging measures only keep permutations
+    /// engaging their receptor within `MAX_SHUFFLES` shuffles
+    fn shuffled_peptide<R: rand::Rng>(
+        peptide: &str,
+        engaging: Option<&KirEngagement>,
+        rng: &mut R,
+    ) -> Option<String> {
+        use rand::seq::SliceRandom;
+
+        let mut residues = peptide.chars().collect::<Vec<char>>();
+        (0..MAX_SHUFFLES).find_map(|_| {
+            residues.shuffle(rng);
+            let shuffled = residues.iter().collect::<String>();
+            match engaging {
+                Some(engaging) if !engaging.engages(&shuffled) => None,
+                _ => Some(shuffled),
             }
+        })
+    }
+
+    /// Null replicates of the index allele's motif sets for every measure and peptide length, the
+    /// shuffled peptides keep the binding weight of the peptide they replace. There are no
+    /// replicates without index motifs.
+    pub fn index_null_sets(
+        &self,
+        index: &ClassI,
+        motif_sets: &MotifSets,
+        measures: &[Measure],
+        pep_lengths: &[usize],
+    ) -> IndexNullSets {
+        use rand::SeedableRng;
+        use rand_chacha::ChaCha8Rng;
+
+        let binding_info = match self.binding_data.get_binding_info(index) {
+            Some(binding_info) => binding_info,
+            _ => return IndexNullSets::new(),
         };
 
-        let index_motifs = self
-            .binding_data
-            .0
+        measures
             .iter()
-            .filter_map(bound_motifs)
-            .collect::<Vec<String>>();
-        let non_index_motifs = self
-            .binding_data
-            .1
+            .flat_map(|measure| pep_lengths.iter().map(move |length| (measure, *length)))
+            .filter_map(|(measure, length)| {
+                let index_motifs = motif_sets
+                    .get(index, &measure.name, length)
+                    .filter(|index_motifs| !index_motifs.is_empty())?;
+                let seed =
+                    calculation_seed(self.seed, &[&index.to_string(), &measure.name], length);
+                let replicates = (0..self.replicates as u64)
+                    .into_par_iter()
+                    .map(|replicate| {
+                        let mut rng = ChaCha8Rng::seed_from_u64(seed.wrapping_add(replicate));
+                        NullSet::new(index_motifs.bound().map(|(weight, peptide)| {
+                            let motif = Self::shuffled_peptide(
+                                binding_info[peptide].seq(),
+                                measure.engaging.as_ref(),
+                                &mut rng,
+                            )
+                            .map(|null_peptide| motif_hash(&null_peptide, &measure.motif_pos));
+                            (motif, weight, peptide)
+                        }))
+                    })
+                    .collect();
+
+                Some(((measure.name.clone(), length), replicates))
+            })
+            .collect()
+    }
+}
+
+/// Null replicates of an index allele's motif sets by measure and peptide length
+pub type IndexNullSets = HashMap<(String, usize), Vec<NullSet>>;
+
+/// Motifs of the shuffled peptides replacing an index allele's bound peptides in a null replicate,
+/// peptides without a shuffled peptide only count towards the weight
+#[derive(Debug, Clone)]
+pub struct NullSet {
+    motifs: MotifSet,
+    weight: f32,
+}
+
+impl NullSet {
+    fn new<I>(peptides: I) -> Self
+    where
+        I: Iterator<Item = (Option<u64>, f32, usize)>,
+    {
+        let mut weight = 0.0;
+        let mut motifs = peptides
+            .filter_map(|(motif, motif_weight, peptide)| {
+                weight += motif_weight;
+                Some((motif?, motif_weight, peptide))
+            })
+            .collect::<Vec<(u64, f32, usize)>>();
+        motifs.sort_unstable_by_key(|(motif, _, _)| *motif);
+
+        let (motifs, (weights, peptides)) = motifs
+            .into_iter()
+            .map(|(motif, weight, peptide)| (motif, (weight, peptide)))
+            .unzip();
+        Self {
+            motifs: MotifSet {
+                motifs,
+                weights,
+                peptides,
+            },
+            weight,
+        }
+    }
+}
+
+impl NullFs {
+    /// Mean null FS of the replicates against the non index motifs with the p-value of the
+    /// observed FS, `(1 + replicates at least as large) / (1 + replicates)`
+    pub fn new(null_sets: &[NullSet], non_index_motifs: &MotifSet, observed: f32) -> Option<Self> {
+        if null_sets.is_empty() {
+            return None;
+        }
+
+        let replicates = null_sets
             .iter()
-            .filter_map(bound_motifs)
-            .collect::<Vec<String>>();
+            .map(|null_set| null_set.motifs.shared_weight(non_index_motifs) / null_set.weight)
+            .collect::<Vec<f32>>();
+        let n = replicates.len() as f32;
+        let extreme = replicates.iter().filter(|fs| **fs >= observed).count() as f32;
+
+        Some(Self {
+            expected: replicates.iter().sum::<f32>() / n,
+            p_value: (1.0 + extreme) / (1.0 + n),
+        })
+    }
+}
+
+/// How much a peptide counts as bound, `hard` counts peptides within the binding cutoff fully while
+/// the soft weights decrease with the %rank or affinity
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum BindingWeight {
+    #[default]
+    Hard,
+    /// Logistic on the %rank (or affinity for nM cutoffs), 0.5 at the cutoff
+    Logistic,
+    /// `1 - rank / cutoff` (or affinity for nM cutoffs)
+    Linear,
+    /// `1 - log(affinity) / log(50000)` regardless of the cutoff, peptides without predicted
+    /// affinity are not bound
+    Affinity,
+}
+
+impl FromStr for BindingWeight {
+    type Err = Error;
 
-        (index_motifs, non_index_motifs)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hard" => Ok(BindingWeight::Hard),
+            "logistic" => Ok(BindingWeight::Logistic),
+            "linear" => Ok(BindingWeight::Linear),
+            "affinity" => Ok(BindingWeight::Affinity),
+            s => Err(Error::UnknownBindingWeight(s.to_string())),
+        }
     }
+}
 
-    /// Counts number of unique bound motifs (if positions provided) or peptides
-    pub fn count_bound(
-        &self,
-        threshold: f32,
-        unique: bool,
-        length: usize,
-        aa_pos: Option<&[usize]>,
-    ) -> (usize, usize) {
-        let is_bound = |item: &&BindingInfo| item.rank() < threshold && item.len() == length;
-
-        let (mut index_motifs, mut non_index_motifs) = match aa_pos {
-            Some(aa_pos) => self.get_motifs(threshold, length, aa_pos),
-            _ => {
-                let index_bound = self
-                    .binding_data
-                    .0
-                    .iter()
-                    .filter(is_bound)
-                    .map(|info| info.seq().to_string())
-                    .collect();
-                let non_index_bound = self
-                    .binding_data
-                    .1
-                    .iter()
-                    .filter(is_bound)
-                    .map(|info| info.seq().to_string())
-                    .collect();
+impl std::fmt::Display for BindingWeight {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let weight = match self {
+            BindingWeight::Hard => "hard",
+            BindingWeight::Logistic => "logistic",
+            BindingWeight::Linear => "linear",
+            BindingWeight::Affinity => "affinity",
+        };
+        write!(f, "{}", weight)
+    }
+}
 
-                (index_bound, non_index_bound)
-            }
+impl BindingWeight {
+    /// Affinity (nM) of the weakest binding peptides in the affinity transform
+    const MAX_AFFINITY: f32 = 50000.0;
+    /// Width of the logistic transform relative to the threshold
+    const LOGISTIC_SCALE: f32 = 0.1;
+    /// Weight from which a peptide is counted as bound in the binding counts
+    const BOUND_WEIGHT: f32 = 0.5;
+    /// Weight below which a peptide is left out of the motif sets, the logistic and affinity weights
+    /// are otherwise positive for nearly every peptide
+    const MIN_WEIGHT: f32 = 0.01;
+
+    /// Weight of a peptide between 0 (not bound) and 1, peptides without the predicted value of the
+    /// cutoff (affinity) are not bound
+    pub fn weight(self, peptide: &PeptideMotif, cutoff: BindingCutoff) -> f32 {
+        let (value, threshold) = match cutoff {
+            BindingCutoff::Rank(rank) => (Some(peptide.rank), rank),
+            BindingCutoff::Affinity(affinity) => (peptide.affinity, affinity),
         };
 
-        if unique {
-            index_motifs.sort();
-            non_index_motifs.sort();
+        match (self, value) {
+            (BindingWeight::Affinity, _) => peptide.affinity.map_or(0.0, |affinity| {
+                (1.0 - affinity.max(1.0).ln() / Self::MAX_AFFINITY.ln()).max(0.0)
+            }),
+            (_, None) => 0.0,
+            (BindingWeight::Hard, Some(value)) if cutoff.binds(value) => 1.0,
+            (BindingWeight::Hard, _) => 0.0,
+            (BindingWeight::Logistic, Some(value)) => {
+                let scale = threshold * Self::LOGISTIC_SCALE;
+                1.0 / (1.0 + ((value - threshold) / scale).exp())
+            }
+            (BindingWeight::Linear, Some(value)) => (1.0 - value / threshold).max(0.0),
+        }
+    }
+}
 
-            index_motifs.dedup();
-            non_index_motifs.dedup();
+/// Cutoff for a peptide to be bound, a %rank below which or an affinity (nM) up to which peptides
+/// are bound
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BindingCutoff {
+    Rank(f32),
+    Affinity(f32),
+}
+
+impl FromStr for BindingCutoff {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidBindingThreshold(s.to_string());
+        match s.trim().strip_suffix("nM") {
+            Some(affinity) => match affinity.trim().parse::<f32>() {
+                Ok(affinity) if affinity > 0.0 => Ok(BindingCutoff::Affinity(affinity)),
+                _ => Err(invalid()),
+            },
+            _ => match s.trim().parse::<f32>() {
+                Ok(rank) if rank > 0.0 && rank <= 100.0 => Ok(BindingCutoff::Rank(rank)),
+                _ => Err(invalid()),
+            },
         }
+    }
+}
 
-        (index_motifs.len(), non_index_motifs.len())
+impl std::fmt::Display for BindingCutoff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BindingCutoff::Rank(rank) => write!(f, "{}", rank),
+            BindingCutoff::Affinity(affinity) => write!(f, "{}nM", affinity),
+        }
     }
+}
 
-    /// Calculates shared peptides motifs based on a threshold for determining bound and whether unique motifs should only be considered,
-    /// the peptides considered can also optionally be filtered based on length
-    pub fn calculate_shared_motifs(
-        &self,
-        motif: &[usize],
-        threshold: f32,
-        unique: bool,
-        length: usize,
-    ) -> (f32, f32) {
-        let (mut index_motifs, mut non_index_motifs) = self.get_motifs(threshold, length, motif);
+impl BindingCutoff {
+    /// Whether a %rank or affinity of the cutoff's kind is bound
+    pub fn binds(self, value: f32) -> bool {
+        match self {
+            BindingCutoff::Rank(rank) => value < rank,
+            BindingCutoff::Affinity(affinity) => value <= affinity,
+        }
+    }
+}
+
+/// Binding criterion, the weak or strong binding %rank threshold of the predictions or a given
+/// %rank or affinity cutoff
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BindingThreshold {
+    Weak,
+    Strong,
+    Cutoff(BindingCutoff),
+}
+
+impl FromStr for BindingThreshold {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "weak" => Ok(BindingThreshold::Weak),
+            "strong" => Ok(BindingThreshold::Strong),
+            cutoff => Ok(BindingThreshold::Cutoff(cutoff.parse()?)),
+        }
+    }
+}
+
+impl BindingThreshold {
+    /// Cutoff of the threshold in the binding predictions
+    pub fn cutoff(self, binding_data: &BindingData) -> BindingCutoff {
+        match self {
+            BindingThreshold::Weak => BindingCutoff::Rank(binding_data.weak_threshold()),
+            BindingThreshold::Strong => BindingCutoff::Rank(binding_data.strong_threshold()),
+            BindingThreshold::Cutoff(cutoff) => cutoff,
+        }
+    }
+}
+
+/// Binding cutoffs of the alleles, alleles without their own cutoff use the default
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlleleCutoffs {
+    default: BindingCutoff,
+    alleles: HashMap<ClassI, BindingCutoff>,
+}
+
+impl AlleleCutoffs {
+    pub fn new(default: BindingCutoff, alleles: HashMap<ClassI, BindingCutoff>) -> Self {
+        Self { default, alleles }
+    }
+
+    pub fn get(&self, allele: &ClassI) -> BindingCutoff {
+        self.alleles.get(allele).copied().unwrap_or(self.default)
+    }
+
+    /// Cutoff of the alleles without their own cutoff
+    pub fn default_cutoff(&self) -> BindingCutoff {
+        self.default
+    }
+}
+
+impl From<BindingCutoff> for AlleleCutoffs {
+    fn from(default: BindingCutoff) -> Self {
+        Self::new(default, HashMap::new())
+    }
+}
+
+/// Hashed motif of a single peptide with its binding predictions
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeptideMotif {
+    pub motif: u64,
+    pub rank: f32,
+    pub affinity: Option<f32>,
+    /// Position of the peptide in the allele's binding predictions
+    pub peptide: usize,
+}
+
+impl PeptideMotif {
+    fn new(peptide: usize, item: &BindingInfo, measure: &Measure) -> Self {
+        Self {
+            motif: motif_hash(item.seq(), &measure.motif_pos),
+            rank: item.rank(),
+            affinity: item.affinity(),
+            peptide,
+        }
+    }
+}
+
+/// Hash of the peptide's residues at the motif positions
+fn motif_hash(peptide: &str, motif_pos: &[usize]) -> u64 {
+    stable_hash(&[&peptide
+        .bytes()
+        .enumerate()
+        .filter(|(i, _)| motif_pos.contains(i))
+        .map(|(_, aa)| aa)
+        .collect::<Vec<u8>>()])
+}
+
+/// Bound motifs of an allele for a single measure and peptide length with the binding weight of
+/// each motif. Motifs are stored hashed and sorted so the motifs shared by two alleles are found by
+/// merging the two sets.
+#[derive(Debug, Default, Clone)]
+pub struct MotifSet {
+    motifs: Vec<u64>,
+    weights: Vec<f32>,
+    /// Positions of the bound peptides in the allele's binding predictions
+    peptides: Vec<usize>,
+}
 
+impl MotifSet {
+    /// Motifs of the peptides with a binding weight of at least `BindingWeight::MIN_WEIGHT`,
+    /// duplicated motifs are kept unless `unique` which keeps the strongest binding
+    pub fn new(
+        peptides: &[PeptideMotif],
+        (cutoff, weighting): (BindingCutoff, BindingWeight),
+        unique: bool,
+    ) -> Self {
+        let mut motifs = peptides
+            .iter()
+            .map(|peptide| {
+                let weight = weighting.weight(peptide, cutoff);
+                (peptide.motif, weight, peptide.peptide)
+            })
+            .filter(|(_, weight, _)| *weight >= BindingWeight::MIN_WEIGHT)
+            .collect::<Vec<(u64, f32, usize)>>();
+
+        motifs.sort_unstable_by_key(|(motif, _, _)| *motif);
         if unique {
-            index_motifs.sort();
-            non_index_motifs.sort();
+            motifs.dedup_by(|(motif, weight, _), (kept, kept_weight, _)| {
+                let duplicate = motif == kept;
+                if duplicate {
+                    *kept_weight = kept_weight.max(*weight);
+                }
+                duplicate
+            });
+        }
 
-            index_motifs.dedup();
-            non_index_motifs.dedup();
+        let (motifs, (weights, peptides)) = motifs
+            .into_iter()
+            .map(|(motif, weight, peptide)| (motif, (weight, peptide)))
+            .unzip();
+        Self {
+            motifs,
+            weights,
+            peptides,
         }
+    }
+
+    /// Number of motifs bound with a weight of at least 0.5, the number of motifs for hard binding
+    /// and of the motifs within the cutoff for the logistic weight
+    pub fn bound_count(&self) -> usize {
+        self.weights
+            .iter()
+            .filter(|weight| **weight >= BindingWeight::BOUND_WEIGHT)
+            .count()
+    }
 
-        let index_shared = index_motifs
+    pub fn is_empty(&self) -> bool {
+        self.motifs.is_empty()
+    }
+
+    /// Summed binding weight of the motifs, the number of motifs for hard binding
+    pub fn weight(&self) -> f32 {
+        self.weights.iter().sum()
+    }
+
+    /// Weight of the motifs in this set also bound by the other set, each motif counting at most as
+    /// much as its strongest binding in the other set. For hard binding this is the number of
+    /// shared motifs.
+    pub fn shared_weight(&self, other: &MotifSet) -> f32 {
+        self.shared_with(other).map(|(_, shared)| shared).sum()
+    }
+
+    /// Weight of every motif in this set with the part of it shared with the other set
+    pub fn shared_with<'a>(&'a self, other: &'a MotifSet) -> impl Iterator<Item = (f32, f32)> + 'a {
+        let mut start = 0;
+        let mut matched = None;
+
+        self.motifs
+            .iter()
+            .zip(self.weights.iter())
+            .map(move |(motif, weight)| {
+                if !matches!(matched, Some((matched_motif, _)) if matched_motif == motif) {
+                    start += other.motifs[start..]
+                        .iter()
+                        .take_while(|other| *other < motif)
+                        .count();
+                    let binding = other.motifs[start..]
+                        .iter()
+                        .zip(other.weights[start..].iter())
+                        .take_while(|(other, _)| *other == motif)
+                        .map(|(_, other_weight)| *other_weight)
+                        .fold(0.0, f32::max);
+                    matched = Some((motif, binding));
+                }
+
+                (
+                    *weight,
+                    weight.min(matched.map_or(0.0, |(_, binding)| binding)),
+                )
+            })
+    }
+
+    /// Summed strongest binding weight of the distinct motifs, the number of distinct motifs for hard
+    /// binding
+    pub fn unique_weight(&self) -> f32 {
+        self.unique_motifs().map(|motif| self.binding(motif)).sum()
+    }
+
+    /// Weight of the distinct motifs bound by both sets, each counting with the weaker of its
+    /// strongest bindings in the two sets
+    pub fn unique_shared_weight(&self, other: &MotifSet) -> f32 {
+        self.unique_motifs()
+            .map(|motif| self.binding(motif).min(other.binding(motif)))
+            .sum()
+    }
+
+    fn unique_motifs(&self) -> impl Iterator<Item = u64> + '_ {
+        self.motifs
             .iter()
-            .filter(|motif| non_index_motifs.contains(motif))
-            .count() as f32;
+            .enumerate()
+            .filter(move |(i, motif)| self.motifs.get(i + 1) != Some(motif))
+            .map(|(_, motif)| *motif)
+    }
+
+    /// Strongest binding weight of the motif in this set, 0 for motifs not in the set
+    pub fn binding(&self, motif: u64) -> f32 {
+        let start = self.motifs.partition_point(|other| *other < motif);
 
-        let non_index_shared = non_index_motifs
+        self.motifs[start..]
             .iter()
-            .filter(|motif| index_motifs.contains(motif))
-            .count() as f32;
+            .zip(self.weights[start..].iter())
+            .take_while(|(other, _)| **other == motif)
+            .map(|(_, weight)| *weight)
+            .fold(0.0, f32::max)
+    }
 
-        (
-            index_shared / index_motifs.len() as f32,
-            non_index_shared / non_index_motifs.len() as f32,
-        )
+    /// Binding weight and position in the binding predictions of every bound peptide
+    pub fn bound(&self) -> impl Iterator<Item = (f32, usize)> + '_ {
+        self.weights
+            .iter()
+            .copied()
+            .zip(self.peptides.iter().copied())
+    }
+
+    /// Weighted fraction of the motifs in this set shared with the other set
+    pub fn fraction_shared(&self, other: &MotifSet) -> f32 {
+        self.shared_weight(other) / self.weight()
+    }
+}
+
+/// Hashed peptide motifs of every allele for each measure and peptide length, hashed once and
+/// reused for the motif sets of every threshold
+#[derive(Debug, Default)]
+pub struct HashedMotifs {
+    peptides: HashMap<(ClassI, String, usize), Vec<PeptideMotif>>,
+}
+
+impl HashedMotifs {
+    /// Motifs of the alleles' peptides, alleles without binding predictions are skipped
+    pub fn new(
+        binding_data: &BindingData,
+        alleles: &HashSet<&ClassI>,
+        measures: &[Measure],
+        pep_lengths: &[usize],
+    ) -> Self {
+        let peptides = binding_data
+            .list_alleles()
+            .into_iter()
+            .filter(|allele| alleles.contains(allele))
+            .collect::<Vec<&ClassI>>()
+            .par_iter()
+            .flat_map(|allele| {
+                let binding_info = binding_data
+                    .get_binding_info(allele)
+                    .map(|info| info.as_slice())
+                    .unwrap_or(&[]);
+
+                measures
+                    .iter()
+                    .flat_map(|measure| {
+                        pep_lengths.iter().map(move |length| {
+                            let engaging = measure.engaging.as_ref();
+                            let peptides = binding_info
+                                .iter()
+                                .enumerate()
+                                .filter(|(_, item)| {
+                                    item.len() == *length
+                                        && !matches!(engaging, Some(engaging) if !engaging.engages(item.seq()))
+                                })
+                                .map(|(peptide, item)| PeptideMotif::new(peptide, item, measure))
+                                .collect::<Vec<PeptideMotif>>();
+
+                            (
+                                ((*allele).clone(), measure.name.to_string(), *length),
+                                peptides,
+                            )
+                        })
+                    })
+                    .collect::<Vec<((ClassI, String, usize), Vec<PeptideMotif>)>>()
+            })
+            .collect();
+
+        Self { peptides }
+    }
+
+    /// Whether any of the peptides has a predicted affinity
+    pub fn has_affinity(&self) -> bool {
+        self.peptides
+            .values()
+            .flatten()
+            .any(|peptide| peptide.affinity.is_some())
+    }
+
+    /// Motif sets bound with the alleles' cutoffs and binding weight
+    pub fn motif_sets(
+        &self,
+        (cutoffs, weighting): (&AlleleCutoffs, BindingWeight),
+        unique: bool,
+    ) -> MotifSets {
+        let sets = self
+            .peptides
+            .par_iter()
+            .map(|(key, peptides)| {
+                let binding = (cutoffs.get(&key.0), weighting);
+                (key.clone(), MotifSet::new(peptides, binding, unique))
+            })
+            .collect();
+
+        MotifSets {
+            sets,
+            cutoffs: cutoffs.clone(),
+        }
+    }
+}
+
+/// Motif sets of every allele for each measure and peptide length, computed once before the
+/// pairwise calculations
+#[derive(Debug)]
+pub struct MotifSets {
+    sets: HashMap<(ClassI, String, usize), MotifSet>,
+    cutoffs: AlleleCutoffs,
+}
+
+impl MotifSets {
+    /// Binding cutoff the motif sets of the allele were bound with
+    pub fn cutoff(&self, allele: &ClassI) -> BindingCutoff {
+        self.cutoffs.get(allele)
+    }
+
+    pub fn get(&self, allele: &ClassI, measure: &str, length: usize) -> Option<&MotifSet> {
+        self.sets
+            .get(&(allele.clone(), measure.to_string(), length))
     }
 }
 
@@ -207,187 +943,237 @@ impl std::str::FromStr for Measure {
                 }
             }
         }
-        Ok(Self { name, motif_pos })
-    }
-}
-
-/// Creates all possible allele combinations from NetMHCpan predictions
-/// TODO: IMPORTANT this is memory intensive
-pub fn create_calc_combs(binding_data: &BindingData) -> Vec<CalculatorComb> {
-    binding_data.list_alleles().par_iter().fold(||
-        Vec::<CalculatorComb>::new(),
-        |mut comb, index_allele| {
-            for non_index_allele in binding_data.list_alleles() {
-                if *index_allele != non_index_allele {
-                    debug!("Storing binding data info for {}, {}", &index_allele, &non_index_allele);
-                    match (binding_data.get_binding_info(index_allele), binding_data.get_binding_info(non_index_allele)) {
-                        (Some(index_data), Some(non_index_data)) => {
-                            let calc_comb = CalculatorComb {
-                                alleles: (index_allele, non_index_allele),
-                                binding_data: (
-                                    index_data,
-                                    non_index_data,
-                                ),
-                            };
-                            comb.push(calc_comb);
-                        },
-                        _ => warn!("Since binding data is not present for both, no fs will be made between {} and {}", &index_allele, &non_index_allele)
-                    }
+        Ok(Self {
+            name,
+            motif_pos,
+            engaging: None,
+        })
+    }
+}
 
-                }
-            }
+impl Measure {
+    /// Measure restricted to the peptides engaging the receptor, named after the receptor
+    pub fn kir_engaging(&self, engaging: KirEngagement) -> Self {
+        Self {
+            name: format!("{}_{}", self.name, engaging.kir),
+            motif_pos: self.motif_pos.clone(),
+            engaging: Some(engaging),
+        }
+    }
+}
+
+/// Ordered pairs of different index and non index alleles, generated lazily so the pairs never have
+/// to be held in memory
+pub fn allele_pairs<'a>(
+    index_alleles: Vec<&'a ClassI>,
+    non_index_alleles: Vec<&'a ClassI>,
+) -> impl Iterator<Item = (&'a ClassI, &'a ClassI)> + Send + 'a {
+    index_alleles.into_iter().flat_map(move |index_allele| {
+        non_index_alleles
+            .clone()
+            .into_iter()
+            .filter(move |non_index_allele| *non_index_allele != index_allele)
+            .map(move |non_index_allele| (index_allele, non_index_allele))
+    })
+}
 
-            comb
-        },
-    )
-        .reduce(|| Vec::new(), |mut a , b| {a.extend(b); a})
+/// Index and non index alleles of the pairs between the index alleles and the alleles of the
+/// cohort, only alleles with binding predictions are kept
+pub fn cohort_allele_pairs<'a>(
+    binding_data: &'a BindingData,
+    index_alleles: &[ClassI],
+    cohort: &[Individual],
+) -> (Vec<&'a ClassI>, Vec<&'a ClassI>) {
+    let cohort_alleles = cohort
+        .iter()
+        .flat_map(|individual| &individual.hla_genotype)
+        .collect::<HashSet<&ClassI>>();
+    let alleles = binding_data.list_alleles().into_iter();
+    let index = alleles
+        .clone()
+        .filter(|allele| index_alleles.contains(allele))
+        .collect();
+    let non_index = alleles
+        .filter(|allele| cohort_alleles.contains(allele))
+        .collect();
+
+    (index, non_index)
 }
 
-/// Make calculations for specific measures and peptide lengths
-pub fn calculate_fs(
-    combinations: &[CalculatorComb],
+/// Number of allele pairs calculated in parallel before their results are sent
+const PAIR_CHUNK: usize = 1024;
+
+/// Make calculations for specific measures and peptide lengths. The pairs are calculated in chunks
+/// and the results of a chunk are sent in the order of the pairs, so the output order does not
+/// depend on the threads. The calculation stops early when the receiver is dropped.
+pub fn calculate_fs<'a, I>(
+    mut allele_pairs: I,
+    motif_sets: &MotifSets,
     measures: &[Measure],
     ligand_map: &KirLigandMap,
-    pep_lengths: &[usize],
-    threshold: f32,
-    unique: bool,
-) -> Vec<CalcFsResult> {
-    combinations
-        .par_iter()
-        .fold(
-            || Vec::<CalcFsResult>::new(),
-            |mut results, comb| {
-                let index = comb.alleles.0.clone();
-                let non_index = comb.alleles.1.clone();
-                let mut index_motifs = ligand_map.get_allele_info(&index);
-                let mut non_index_motifs = ligand_map.get_allele_info(&non_index);
-
-                index_motifs.sort();
-                non_index_motifs.sort();
-
-                let index_ligand_motif = match index_motifs.iter().next() {
-                    Some(info) => Some(info.motif().clone()),
-                    _ => None,
-                };
-
-                let non_index_ligand_motif = match non_index_motifs.iter().next() {
-                    Some(info) => Some(info.motif().clone()),
-                    _ => None,
-                };
-
-                measures.iter().for_each(|measure_group| {
-                    pep_lengths.iter().for_each(|pep_length| {
+    ligand_policy: AmbiguityPolicy,
+    options: &FsOptions,
+    sender: SyncSender<CalcFsResult>,
+) where
+    I: Iterator<Item = (&'a ClassI, &'a ClassI)> + Send,
+{
+    let ligand_table = ligand_map.version().unwrap_or("NA");
+    let ligand_motif = |allele: &ClassI| {
+        ligand_map
+            .lookup(allele)
+            .resolve(ligand_policy)
+            .info()
+            .map(|info| info.motif().clone())
+    };
+
+    let pair_results = |(index, non_index): (&ClassI, &ClassI),
+                        null_sets: &HashMap<ClassI, IndexNullSets>| {
+        let index_ligand_motif = ligand_motif(index);
+        let non_index_ligand_motif = ligand_motif(non_index);
+
+        measures
+            .iter()
+            .flat_map(|measure_group| {
+                options
+                    .pep_lengths
+                    .iter()
+                    .map(|pep_length| {
                         debug!(
                             "Calculating FS for index {}, non index {}, measure {}  and length {}",
                             &index, &non_index, &measure_group.name, &pep_length
                         );
                         let measure = measure_group.name.to_string();
-                        let motif = &measure_group.motif_pos;
-                        let (index_bound, non_index_bound) =
-                            comb.count_bound(threshold, unique, *pep_length, Some(motif));
-                        let (fraction_shared, _) =
-                            comb.calculate_shared_motifs(motif, threshold, unique, *pep_length);
-
-                        let result = CalcFsResult {
+                        let empty = MotifSet::default();
+                        let index_motifs = motif_sets
+                            .get(index, &measure, *pep_length)
+                            .unwrap_or(&empty);
+                        let non_index_motifs = motif_sets
+                            .get(non_index, &measure, *pep_length)
+                            .unwrap_or(&empty);
+
+                        let interval = options.bootstrap.and_then(|bootstrap| {
+                            let shared = index_motifs
+                                .shared_with(non_index_motifs)
+                                .collect::<Vec<(f32, f32)>>();
+                            let seed = bootstrap.pair_seed(index, non_index, &measure, *pep_length);
+                            bootstrap.fraction_shared(&shared, seed)
+                        });
+                        let fraction_shared = index_motifs.fraction_shared(non_index_motifs);
+                        let null_fs = null_sets
+                            .get(index)
+                            .and_then(|index_null_sets| {
+                                index_null_sets.get(&(measure.clone(), *pep_length))
+                            })
+                            .and_then(|null_sets| {
+                                NullFs::new(null_sets, non_index_motifs, fraction_shared)
+                            });
+
+                        CalcFsResult {
                             measure,
                             index: index.clone(),
                             non_index: non_index.clone(),
                             index_ligand_motif: index_ligand_motif.clone(),
                             non_index_ligand_motif: non_index_ligand_motif.clone(),
                             fraction_shared,
+                            non_index_fraction_shared: None,
+                            jaccard: None,
+                            dice: None,
+                            overlap_coefficient: None,
+                            shared_motifs: None,
+                            fs_ci_lower: interval.map(|interval| interval.lower),
+                            fs_ci_upper: interval.map(|interval| interval.upper),
+                            fs_se: interval.map(|interval| interval.se),
+                            expected_fs: null_fs.map(|null_fs| null_fs.expected),
+                            fs_p_value: null_fs.map(|null_fs| null_fs.p_value),
                             peptide_length: *pep_length,
-                            index_bound,
-                            non_index_bound,
-                        };
+                            threshold: motif_sets.cutoff(index),
+                            non_index_threshold: motif_sets.cutoff(non_index),
+                            index_bound: index_motifs.bound_count(),
+                            non_index_bound: non_index_motifs.bound_count(),
+                            ligand_table: ligand_table.to_string(),
+                            non_index_weight: non_index_motifs.weight(),
+                            non_index_shared: non_index_motifs.shared_weight(index_motifs),
+                            unique_index_weight: index_motifs.unique_weight(),
+                            unique_non_index_weight: non_index_motifs.unique_weight(),
+                            unique_shared: index_motifs.unique_shared_weight(non_index_motifs),
+                        }
+                    })
+                    .collect::<Vec<CalcFsResult>>()
+            })
+            .collect::<Vec<CalcFsResult>>()
+    };
+
+    // Null replicates are only kept for the index alleles of the current chunk, the pairs of an
+    // index allele are consecutive so its replicates are drawn once
+    let mut null_sets = HashMap::<ClassI, IndexNullSets>::new();
+    loop {
+        let chunk = allele_pairs
+            .by_ref()
+            .take(PAIR_CHUNK)
+            .collect::<Vec<(&ClassI, &ClassI)>>();
+        if chunk.is_empty() {
+            break;
+        }
 
-                        results.push(result);
-                    });
+        if let Some(null_model) = &options.null_model {
+            let indexes = chunk
+                .iter()
+                .map(|(index, _)| *index)
+                .collect::<HashSet<&ClassI>>();
+            null_sets.retain(|index, _| indexes.contains(index));
+            indexes.into_iter().for_each(|index| {
+                null_sets.entry(index.clone()).or_insert_with(|| {
+                    null_model.index_null_sets(index, motif_sets, measures, &options.pep_lengths)
                 });
+            });
+        }
 
-                results
-            },
-        )
-        .reduce(
-            || Vec::new(),
-            |mut a, b| {
-                a.extend(b);
-                a
-            },
-        )
+        let results = chunk
+            .into_par_iter()
+            .flat_map_iter(|pair| pair_results(pair, &null_sets))
+            .collect::<Vec<CalcFsResult>>();
+        let sent = results
+            .into_iter()
+            // The unsent result is only returned when the receiver has been dropped
+            .try_for_each(|result| sender.send(result).map_err(drop));
+        if sent.is_err() {
+            warn!("Stopped FS calculations as results could no longer be written");
+            break;
+        }
+    }
 }
 
 /* Cohort */
 
-// TODO: Need to unit test
-pub fn create_index_fs_map(
-    index_alleles: Vec<ClassI>,
-    fs_results: Vec<CalcFsResult>,
-) -> HashMap<ClassI, Vec<CalcFsResult>> {
-    index_alleles
-        .into_par_iter()
-        .fold(|| HashMap::new(), |mut index_calc_map, index_allele| {
-            let index_results = fs_results.iter().filter(|result| result.index == index_allele).cloned().collect::<Vec<CalcFsResult>>();
-            if index_results.is_empty() {
-                warn!("Index allele '{}' was not present in the NetMHCpan results and no calculations for this allele will be performed.", &index_allele);
-            } else {
-                index_calc_map.insert(index_allele, index_results);
-            }
-            index_calc_map
-        })
-        .reduce(|| HashMap::new(), | mut a , b| {a.extend(b); a})
-}
-
 #[derive(Debug)]
 pub struct IndexCache {
     pub indexes: HashSet<ClassI>,
     pub index_motifs: HashMap<ClassI, LigandMotif>,
-    pub index_act_kirs: HashMap<ClassI, Vec<Kir>>,
-    pub index_inh_kirs: HashMap<ClassI, Vec<Kir>>,
     pub fs_cache: HashMap<(String, usize), HashMap<(ClassI, ClassI), CalcFsResult>>,
+    /// Binding threshold of the index allele FS results
+    pub thresholds: HashMap<ClassI, BindingCutoff>,
+    pub ligand_table: String,
 }
 
 impl IndexCache {
     pub fn new(
         index_alleles: Vec<ClassI>,
         fs_result: Vec<CalcFsResult>,
-        kir_interactions: &HashMap<Kir, Vec<LigandMotif>>,
-        measures: &[Measure],
-        pep_lengths: &[usize],
+        ligand_table: &str,
     ) -> Self {
         let mut indexes = HashSet::new();
         let mut index_motifs = HashMap::new();
-        let mut index_act_kirs = HashMap::<ClassI, Vec<Kir>>::new();
-        let mut index_inh_kirs = HashMap::<ClassI, Vec<Kir>>::new();
+        let mut thresholds = HashMap::new();
         let mut fs_cache =
             HashMap::<(String, usize), HashMap<(ClassI, ClassI), CalcFsResult>>::new();
 
-        let measure_combs = measures.iter().fold(Vec::new(), |mut cache_keys, measure| {
-            pep_lengths
-                .iter()
-                .for_each(|length| cache_keys.push((measure.name.to_string(), *length)));
-            cache_keys
-        });
-
         fs_result.into_iter().for_each(|result| {
             if index_alleles.contains(&result.index) {
                 let index_allele = result.index.clone();
+                thresholds.insert(index_allele.clone(), result.threshold);
                 if !index_motifs.contains_key(&index_allele) {
                     indexes.insert(index_allele.clone());
                     if let Some(index_motif) = result.index_ligand_motif.clone() {
-                        kir_interactions.iter().for_each(|(kir, kir_motifs)| {
-                            if kir_motifs.contains(&index_motif) {
-                                if kir.is_activating() {
-                                    let act_kirs =
-                                        index_act_kirs.entry(index_allele.clone()).or_default();
-                                    act_kirs.push(kir.clone())
-                                } else if kir.is_inhibitory() {
-                                    let inh_kirs =
-                                        index_inh_kirs.entry(index_allele.clone()).or_default();
-                                    inh_kirs.push(kir.clone())
-                                }
-                            }
-                        });
-
                         index_motifs.insert(index_allele.clone(), index_motif);
                     }
                 }
@@ -410,9 +1196,9 @@ impl IndexCache {
         Self {
             indexes,
             index_motifs,
-            index_act_kirs,
-            index_inh_kirs,
             fs_cache,
+            thresholds,
+            ligand_table: ligand_table.to_string(),
         }
     }
 }
@@ -424,49 +1210,171 @@ pub struct CohortResult {
     pub id: String,
     pub measure: String,
     #[serde(serialize_with = "crate::io::ser::optional_float_serialize")]
-    pub fs: Option<f32>,
+    pub fs: Option<f32>,
+    /// Bootstrap confidence interval and standard error of the genotype allele with the highest FS
+    #[serde(serialize_with = "crate::io::ser::optional_float_serialize")]
+    pub fs_ci_lower: Option<f32>,
+    #[serde(serialize_with = "crate::io::ser::optional_float_serialize")]
+    pub fs_ci_upper: Option<f32>,
+    #[serde(serialize_with = "crate::io::ser::optional_float_serialize")]
+    pub fs_se: Option<f32>,
+    /// Highest selected overlap metrics among the genotype alleles, NA when not selected
+    #[serde(serialize_with = "crate::io::ser::optional_float_serialize")]
+    pub non_index_fs: Option<f32>,
+    #[serde(serialize_with = "crate::io::ser::optional_float_serialize")]
+    pub jaccard_fs: Option<f32>,
+    #[serde(serialize_with = "crate::io::ser::optional_float_serialize")]
+    pub dice_fs: Option<f32>,
+    #[serde(serialize_with = "crate::io::ser::optional_float_serialize")]
+    pub overlap_fs: Option<f32>,
+    #[serde(serialize_with = "crate::io::ser::optional_float_serialize")]
+    pub shared_motifs: Option<f32>,
     #[serde(serialize_with = "crate::io::ser::optional_float_serialize")]
     pub ikir_fs: Option<f32>,
     #[serde(serialize_with = "crate::io::ser::optional_float_serialize")]
     pub akir_fs: Option<f32>,
-    // TODO: Need to move LILRB outside of this output as it is independent of measure or peptide length
-    // and is inefficiently calculated
+    /// Summed copy number of the expressed receptors shared by the index allele and the genotype
+    /// allele giving the KIR-aware FS, a covariate to stratify the KIR-aware FS by
+    #[serde(serialize_with = "crate::io::ser::optional_count_serialize")]
+    pub ikir_copies: Option<u8>,
+    #[serde(serialize_with = "crate::io::ser::optional_count_serialize")]
+    pub akir_copies: Option<u8>,
+    /// KIR-aware FS weighted by the strength of the shared receptor interactions
     #[serde(serialize_with = "crate::io::ser::optional_float_serialize")]
-    pub lilrb1: Option<f32>,
+    pub ikir_weighted_fs: Option<f32>,
     #[serde(serialize_with = "crate::io::ser::optional_float_serialize")]
-    pub lilrb2: Option<f32>,
+    pub akir_weighted_fs: Option<f32>,
     pub peptide_length: usize,
+    #[serde(
+        serialize_with = "crate::io::ser::optional_display_serialize",
+        skip_deserializing
+    )]
+    pub threshold: Option<BindingCutoff>,
     pub alleles_considered: usize,
+    #[serde(serialize_with = "crate::io::ser::optional_display_serialize")]
+    pub kir_haplotypes: Option<HaplotypePair>,
+    #[serde(serialize_with = "crate::io::ser::optional_display_serialize")]
+    pub cen_motifs: Option<HaplotypePair>,
+    #[serde(serialize_with = "crate::io::ser::optional_display_serialize")]
+    pub tel_motifs: Option<HaplotypePair>,
+    pub ligand_table: String,
+}
+
+/// FS measures of a single genotype allele compared to the index allele
+struct AlleleKirFs<'a> {
+    fs: f32,
+    fs_result: &'a CalcFsResult,
+    ikir: f32,
+    akir: f32,
+    ikir_copies: u8,
+    akir_copies: u8,
+    ikir_weighted: f32,
+    akir_weighted: f32,
 }
 
-/// Temporary function to get kirs bound by allele
-fn get_bound_kirs(
-    motif_interactions: &HashMap<Kir, Vec<LigandMotif>>,
-    motif: &LigandMotif,
-) -> Vec<Kir> {
-    motif_interactions
+/// Highest FS among the genotype alleles, undefined (NaN) FS of alleles without bound motifs (e.g.
+/// at strict thresholds) are skipped
+fn max_allele_fs<F>(allele_fs: &[AlleleKirFs], measure: F) -> Option<f32>
+where
+    F: Fn(&AlleleKirFs) -> f32,
+{
+    allele_fs
         .iter()
-        .fold(Vec::new(), |mut result, (kir, kir_motifs)| {
-            if kir_motifs.contains(motif) {
-                result.push(kir.clone())
-            };
-            result
+        .map(measure)
+        .filter(|fs| !fs.is_nan())
+        .max_by(|a, b| a.partial_cmp(b).expect("Tried to compare a NaN"))
+}
+
+/// Matched receptor copies of the genotype allele with the highest KIR-aware FS, the most copies
+/// among alleles with the same FS
+fn max_allele_copies<F>(allele_fs: &[AlleleKirFs], kir_fs: F) -> Option<u8>
+where
+    F: Fn(&AlleleKirFs) -> (f32, u8),
+{
+    allele_fs
+        .iter()
+        .map(kir_fs)
+        .filter(|(fs, _)| !fs.is_nan())
+        .max_by(|a, b| {
+            a.0.partial_cmp(&b.0)
+                .expect("Tried to compare a NaN")
+                .then(a.1.cmp(&b.1))
         })
+        .map(|(_, copies)| copies)
+}
+
+/// Highest overlap metric among the genotype alleles, undefined (NaN) values of empty motif sets
+/// are skipped
+fn max_allele_metric<F>(allele_fs: &[AlleleKirFs], metric: F) -> Option<f32>
+where
+    F: Fn(&CalcFsResult) -> Option<f32>,
+{
+    allele_fs
+        .iter()
+        .filter_map(|result| metric(result.fs_result))
+        .filter(|value| !value.is_nan())
+        .max_by(|a, b| a.partial_cmp(b).expect("Tried to compare a NaN"))
+}
+
+/// KIR-aware FS of a genotype allele through the KIR receptors engaging the index allele. The FS is
+/// kept when the individual expresses a receptor also engaging the genotype allele and is 0
+/// otherwise, without any receptor engaging the index allele the FS is kept as is. The copies and
+/// the strongest score of the matched receptors are returned with it, a receptor's score is only
+/// as strong as its weaker engagement of the two alleles.
+fn kir_receptor_fs(
+    fs: f32,
+    receptors: &[&KirReceptor],
+    (index, allele): (HlaLigand, HlaLigand),
+    individual: &Individual,
+    expressed_kirs: &[Kir],
+) -> (f32, u8, Option<f32>) {
+    let matched = receptors
+        .iter()
+        .filter(|receptor| expressed_kirs.contains(&receptor.kir))
+        .filter_map(|receptor| Some((&receptor.kir, receptor.interaction.score(index, allele)?)))
+        .collect::<Vec<(&Kir, f32)>>();
+
+    let copies = matched
+        .iter()
+        .map(|(kir, _)| individual.kir_copy_number(kir))
+        .sum();
+    let strength = matched
+        .iter()
+        .map(|(_, score)| *score)
+        .max_by(|a, b| a.partial_cmp(b).expect("Tried to compare a NaN"));
+
+    if matched.is_empty() && !receptors.is_empty() {
+        (0.0, copies, strength)
+    } else {
+        (fs, copies, strength)
+    }
+}
+
+/// Weights the KIR-aware FS by the interaction strength, values without any shared receptor are
+/// kept as is
+fn weight_by_strength(kir_fs: f32, strength: Option<f32>) -> f32 {
+    match strength {
+        Some(strength) => kir_fs * strength,
+        _ => kir_fs,
+    }
 }
 
 // TODO: Deal with possible errors and also some are never going to return an error
-// TODO: Issue when no FS result still LILRB should be produced
 pub fn calculate_index_cohort_fs(
     index_cache: IndexCache,
     cohort: &[Individual],
-    kir_motif_interactions: &HashMap<Kir, Vec<LigandMotif>>,
-    lilrb_scores: &[LilrbScore],
+    kir_reference: &KirReference,
 ) -> Vec<CohortResult> {
     use LigandMotif::*;
 
-    cohort.par_iter().fold(|| Vec::new(), |mut results, individual| {
+    let kir_allotypes = &kir_reference.allotypes;
+
+    cohort.par_iter().fold(Vec::new, |mut results, individual| {
         let genotype = &individual.hla_genotype;
-        let kir_bound = individual.kir_bound_motifs(&kir_motif_interactions);
+        let expressed_kirs = individual.expressed_kirs(kir_allotypes);
+        let kir_haplotypes = individual.kir_haplotypes();
+        let cen_motifs = individual.cen_motifs();
+        let tel_motifs = individual.tel_motifs();
         debug!("Started processing individual {}", &individual.id);
 
 
@@ -477,133 +1385,102 @@ pub fn calculate_index_cohort_fs(
                 index_cache.indexes.iter().for_each(|index| {
                     debug!("Started processing {} for peptide lengths {} with index allele {} and individual {}", &measure, &length, &index, &individual.id);
                     let index_motif = index_cache.index_motifs.get(index);
-                    let index_lilrb_scores = lilrb_scores.iter().get_matching(&index);
-                    if index_lilrb_scores.is_empty() {
-                        warn!("Index allele '{}', has no associated LILRB binding similarity scores", &index);
-                    }
-
-                    debug!("Got lilrb scores for index allele");
-                    let index_act_kirs = match index_cache.index_act_kirs.get(index) {
-                        Some(act_kirs) => act_kirs.clone(),
-                        _ => Vec::new(),
+                    // The configured KIR receptors engaging the index allele
+                    let index_receptors = |engages: fn(&Kir) -> bool| {
+                        kir_reference
+                            .receptors
+                            .iter()
+                            .filter(|receptor| engages(&receptor.kir))
+                            .filter(|receptor| receptor.interaction.engagement((index, index_motif)).is_some())
+                            .collect::<Vec<&KirReceptor>>()
                     };
+                    let act_receptors = index_receptors(Kir::is_activating);
+                    let inh_receptors = index_receptors(Kir::is_inhibitory);
 
-                    let index_inh_kirs = match index_cache.index_inh_kirs.get(index) {
-                        Some(inh_kirs) => inh_kirs.clone(),
-                        _ => Vec::new(),
-                    };
-
-                    let (fs, ikir_fs, akir_fs, lilrb1, lilrb2) = genotype.iter().fold(
-                        (Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new()),
-                        |(mut fs, mut ikir_fs, mut akir_fs, mut lilrb1, mut lilrb2), genotype_allele| {
-
-                            if !index_lilrb_scores.is_empty() && index != genotype_allele {
-                                let lilrb_scores = index_lilrb_scores.iter().copied().get_matching(genotype_allele);
-
-                                match lilrb_scores.len() {
-                                    0 => { warn!("No LILRB binding scores found for allele '{}' in individual {}", &genotype_allele, individual.id) },
-                                    1 => {
-                                        lilrb1.push(lilrb_scores[0].lilrb1_score);
-                                        lilrb2.push(lilrb_scores[0].lilrb2_score);
-                                    },
-                                    n => {
-                                        lilrb1.push(lilrb_scores.iter().map(|score| score.lilrb1_score ).sum::<f32>() / n as f32);
-                                        lilrb2.push(lilrb_scores.iter().map(|score| score.lilrb2_score ).sum::<f32>() / n as f32);
-                                    },
-                                }
-                            }
-
+                    let allele_fs = genotype.iter().fold(
+                        Vec::new(),
+                        |mut allele_fs, genotype_allele| {
                             if let Some(fs_result) =
                                 calc_result.get(&(index.clone(), genotype_allele.clone()))
                             {
                                 let initial = fs_result.fraction_shared;
-                                let mut akir = initial;
-                                let mut ikir = initial;
-
-                                match (index_motif, &fs_result.non_index_ligand_motif) {
-                                    (Some(Unclassified), Some(Unclassified)) => {
-                                        akir = 1.0;
-                                        ikir = 1.0
-                                    }
-                                    (Some(index_motif), Some(gene_motif)) => {
-                                        let genotype_bound_kirs =
-                                            get_bound_kirs(&kir_motif_interactions, &gene_motif);
-
-                                        let act_bound = genotype_bound_kirs
-                                            .iter()
-                                            .filter(|kir| index_act_kirs.contains(kir))
-                                            .collect::<Vec<&Kir>>();
-                                        let inh_bound = genotype_bound_kirs
-                                            .iter()
-                                            .filter(|kir| index_inh_kirs.contains(kir))
-                                            .collect::<Vec<&Kir>>();
-
-                                        let act_n = act_bound
-                                            .iter()
-                                            .filter(|kir| individual.kir_genotype.contains(kir))
-                                            .count();
-                                        let inh_n = act_bound
-                                            .iter()
-                                            .filter(|kir| individual.kir_genotype.contains(kir))
-                                            .count();
-
-                                        if act_n == 0
-                                            && !(index_act_kirs.is_empty() && act_bound.is_empty())
-                                        {
-                                            akir = 0.0;
+                                let ((akir, akir_copies, akir_strength), (ikir, ikir_copies, ikir_strength)) =
+                                    match (index_motif, &fs_result.non_index_ligand_motif) {
+                                        (Some(Unclassified), Some(Unclassified)) => ((1.0, 0, None), (1.0, 0, None)),
+                                        (Some(index_motif), Some(gene_motif)) => {
+                                            let ligands =
+                                                ((index, Some(index_motif)), (genotype_allele, Some(gene_motif)));
+                                            (
+                                                kir_receptor_fs(initial, &act_receptors, ligands, individual, &expressed_kirs),
+                                                kir_receptor_fs(initial, &inh_receptors, ligands, individual, &expressed_kirs),
+                                            )
                                         }
-
-                                        if inh_n == 0
-                                            && !(index_inh_kirs.is_empty() && inh_bound.is_empty())
-                                        {
-                                            ikir = 0.0;
-                                        }
-                                    }
-                                    // Should this be only (None, None) and throw or ignore otherwise
-                                    _ => {
-                                        akir = 0.0;
-                                        ikir = 0.0;
-                                    }
-                                }
-
-                                fs.push(initial);
-                                ikir_fs.push(ikir);
-                                akir_fs.push(akir);
+                                        // Should this be only (None, None) and throw or ignore otherwise
+                                        _ => ((0.0, 0, None), (0.0, 0, None)),
+                                    };
+
+                                allele_fs.push(AlleleKirFs {
+                                    fs: initial,
+                                    fs_result,
+                                    ikir,
+                                    akir,
+                                    ikir_copies,
+                                    akir_copies,
+                                    ikir_weighted: weight_by_strength(ikir, ikir_strength),
+                                    akir_weighted: weight_by_strength(akir, akir_strength),
+                                });
                             }
 
-                            (fs, ikir_fs, akir_fs, lilrb1, lilrb2)
+                            allele_fs
                         },
                     );
 
-                    let alleles_considered = fs.len();
-
-                    let fs = fs
-                        .into_iter()
-                        .max_by(|a, b| a.partial_cmp(b).expect("Tried to compare a NaN"));
-                    let ikir_fs = ikir_fs
-                        .into_iter()
-                        .max_by(|a, b| a.partial_cmp(b).expect("Tried to compare a NaN"));
-                    let akir_fs = akir_fs
-                        .into_iter()
-                        .max_by(|a, b| a.partial_cmp(b).expect("Tried to compare a NaN"));
-                    let lilrb1 = lilrb1
-                        .into_iter()
-                        .max_by(|a, b| a.partial_cmp(b).expect("Tried to compare a NaN"));
-                    let lilrb2 = lilrb2
-                        .into_iter()
-                        .max_by(|a, b| a.partial_cmp(b).expect("Tried to compare a NaN"));
+                    let alleles_considered = allele_fs.len();
+
+                    let fs = max_allele_fs(&allele_fs, |result| result.fs);
+                    let max_fs_result = allele_fs
+                        .iter()
+                        .filter(|result| !result.fs.is_nan())
+                        .max_by(|a, b| a.fs.partial_cmp(&b.fs).expect("Tried to compare a NaN"))
+                        .map(|result| result.fs_result);
+                    let non_index_fs = max_allele_metric(&allele_fs, |result| result.non_index_fraction_shared);
+                    let jaccard_fs = max_allele_metric(&allele_fs, |result| result.jaccard);
+                    let dice_fs = max_allele_metric(&allele_fs, |result| result.dice);
+                    let overlap_fs = max_allele_metric(&allele_fs, |result| result.overlap_coefficient);
+                    let shared_motifs = max_allele_metric(&allele_fs, |result| result.shared_motifs);
+                    let ikir_fs = max_allele_fs(&allele_fs, |result| result.ikir);
+                    let akir_fs = max_allele_fs(&allele_fs, |result| result.akir);
+                    let ikir_weighted_fs = max_allele_fs(&allele_fs, |result| result.ikir_weighted);
+                    let akir_weighted_fs = max_allele_fs(&allele_fs, |result| result.akir_weighted);
+                    let ikir_copies = max_allele_copies(&allele_fs, |result| (result.ikir, result.ikir_copies));
+                    let akir_copies = max_allele_copies(&allele_fs, |result| (result.akir, result.akir_copies));
 
                     let result = CohortResult {
                         index: index.clone(),
                         id: individual.id.to_string(),
                         measure: measure.to_string(),
                         fs,
+                        fs_ci_lower: max_fs_result.and_then(|result| result.fs_ci_lower),
+                        fs_ci_upper: max_fs_result.and_then(|result| result.fs_ci_upper),
+                        fs_se: max_fs_result.and_then(|result| result.fs_se),
+                        non_index_fs,
+                        jaccard_fs,
+                        dice_fs,
+                        overlap_fs,
+                        shared_motifs,
                         ikir_fs,
                         akir_fs,
-                        lilrb1,
-                        lilrb2,
+                        ikir_copies,
+                        akir_copies,
+                        ikir_weighted_fs,
+                        akir_weighted_fs,
                         peptide_length: *length,
+                        threshold: index_cache.thresholds.get(index).copied(),
                         alleles_considered,
+                        kir_haplotypes,
+                        cen_motifs,
+                        tel_motifs,
+                        ligand_table: index_cache.ligand_table.to_string(),
                     };
                     results.push(result);
                 });
@@ -611,7 +1488,258 @@ pub fn calculate_index_cohort_fs(
 
         results
     })
-        .reduce(|| Vec::new(), | mut a , b| {a.extend(b); a})
+        .reduce(Vec::new, | mut a , b| {a.extend(b); a})
+}
+
+/* KIR */
+
+/// KIR reference data used in the cohort calculations, the KIR-aware FS is calculated through the
+/// receptors of the binding table
+#[derive(Debug)]
+pub struct KirReference {
+    pub binding: KirBindingTable,
+    pub receptors: Vec<KirReceptor>,
+    pub allotypes: HashMap<Kir, KirExpression>,
+    pub ligand_scores: KirLigandScores,
+}
+
+impl KirReference {
+    pub fn new(
+        binding: KirBindingTable,
+        allotypes: HashMap<Kir, KirExpression>,
+        ligand_scores: KirLigandScores,
+    ) -> Self {
+        Self {
+            receptors: KirReceptor::from_kir_binding(&binding),
+            binding,
+            allotypes,
+            ligand_scores,
+        }
+    }
+}
+
+/// KIR and ligand motif interactions with the strength of each interaction
+#[derive(Debug, Default)]
+pub struct KirBindingTable {
+    pub motifs: HashMap<Kir, Vec<LigandMotif>>,
+    pub strengths: HashMap<(Kir, LigandMotif), f32>,
+}
+
+impl KirBindingTable {
+    pub fn insert(&mut self, kir: Kir, motif: LigandMotif, strength: f32) {
+        let motifs = self.motifs.entry(kir.clone()).or_default();
+        if !motifs.contains(&motif) {
+            motifs.push(motif.clone())
+        }
+        self.strengths.insert((kir, motif), strength);
+    }
+
+    /// Strength of the interaction, 0.0 when the KIR does not bind the motif
+    pub fn strength(&self, kir: &Kir, motif: &LigandMotif) -> f32 {
+        self.strengths
+            .get(&(kir.clone(), motif.clone()))
+            .copied()
+            .unwrap_or(0.0)
+    }
+}
+
+/* KIR peptide engagement */
+
+/// Whether the peptide residues of a rule enable or abolish receptor binding
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum PeptideEffect {
+    Enable,
+    Abolish,
+}
+
+impl FromStr for PeptideEffect {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "enable" => Ok(PeptideEffect::Enable),
+            "abolish" => Ok(PeptideEffect::Abolish),
+            s => Err(Error::InvalidKirPeptideRule(s.to_string())),
+        }
+    }
+}
+
+/// Residues at a 9mer peptide position (1-based) that enable or abolish binding of a receptor
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct PeptideRule {
+    pub kir: Kir,
+    pub position: usize,
+    pub residues: String,
+    pub effect: PeptideEffect,
+}
+
+impl PeptideRule {
+    /// Residue of the peptide at the rule position, counted from the C-terminus for peptides that
+    /// are not 9mers
+    fn residue(&self, peptide: &str) -> Option<char> {
+        let from_end = 9usize.checked_sub(self.position)?;
+        let index = peptide.len().checked_sub(from_end + 1)?;
+
+        peptide.chars().nth(index)
+    }
+}
+
+/// Peptide rules of a single receptor
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct KirEngagement {
+    pub kir: Kir,
+    pub rules: Vec<PeptideRule>,
+}
+
+impl KirEngagement {
+    /// A peptide engages the receptor unless an abolishing rule matches or the residue at a position
+    /// with enabling rules is not one of the enabling residues
+    pub fn engages(&self, peptide: &str) -> bool {
+        let matches = |rule: &PeptideRule| matches!(rule.residue(peptide), Some(residue) if rule.residues.contains(residue));
+
+        let abolished = self
+            .rules
+            .iter()
+            .filter(|rule| rule.effect == PeptideEffect::Abolish)
+            .any(matches);
+
+        let enabling = self
+            .rules
+            .iter()
+            .filter(|rule| rule.effect == PeptideEffect::Enable)
+            .collect::<Vec<&PeptideRule>>();
+
+        let enabled = enabling.iter().all(|rule| {
+            enabling
+                .iter()
+                .filter(|other| other.position == rule.position)
+                .any(|other| matches(other))
+        });
+
+        !abolished && enabled
+    }
+}
+
+/// Peptide rules of all receptors in the order they were listed
+#[derive(Debug, Default)]
+pub struct KirPeptideRules {
+    pub rules: Vec<PeptideRule>,
+}
+
+impl KirPeptideRules {
+    /// Rules grouped by receptor, receptors are in the order they first appear
+    pub fn engagements(&self) -> Vec<KirEngagement> {
+        self.rules
+            .iter()
+            .fold(Vec::<KirEngagement>::new(), |mut engagements, rule| {
+                match engagements
+                    .iter_mut()
+                    .find(|engagement| engagement.kir == rule.kir)
+                {
+                    Some(engagement) => engagement.rules.push(rule.clone()),
+                    _ => engagements.push(KirEngagement {
+                        kir: rule.kir.clone(),
+                        rules: vec![rule.clone()],
+                    }),
+                }
+                engagements
+            })
+    }
+}
+
+/* KIR ligand similarity */
+
+/// Entry of the KIR ligand similarity table, either a ligand class (motifs grouped as C1, C2, A3,
+/// A11, Bw4 and Bw6) or an allele scored separately from its motif (e.g. the C1-like B4601)
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum KirLigandKey {
+    Class(String),
+    Allele(String),
+}
+
+impl FromStr for KirLigandKey {
+    type Err = NomenclatureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s {
+            "C1" | "C2" | "A3" | "A11" | "Bw4" | "Bw6" => Ok(KirLigandKey::Class(s.to_string())),
+            s if s.len() == 5 && s[1..].chars().all(|c| c.is_ascii_digit()) => {
+                let allele = format!("{}:{}", &s[..3], &s[3..]).parse::<ClassI>()?;
+                Ok(KirLigandKey::Allele(two_field(&allele)))
+            }
+            s => Err(NomenclatureError::UnknownLigandMotif(s.to_string())),
+        }
+    }
+}
+
+impl KirLigandKey {
+    /// Ligand class of the motif, Bw4 motifs are not separated by residue 80
+    pub fn from_motif(motif: &LigandMotif) -> Option<Self> {
+        use LigandMotif::*;
+        let class = match motif {
+            A11 => "A11",
+            A3 => "A3",
+            Bw4_80T | Bw4_80I => "Bw4",
+            Bw6 => "Bw6",
+            C1 => "C1",
+            C2 => "C2",
+            Unclassified => return None,
+        };
+
+        Some(KirLigandKey::Class(class.to_string()))
+    }
+}
+
+pub(crate) fn two_field(allele: &ClassI) -> String {
+    allele
+        .to_string()
+        .split(':')
+        .take(2)
+        .collect::<Vec<&str>>()
+        .join(":")
+}
+
+/// Pairwise KIR ligand scores, lower scores are more similar ligands (0 for equivalent ligands)
+#[derive(Debug, Default)]
+pub struct KirLigandScores {
+    pub scores: HashMap<(KirLigandKey, KirLigandKey), f32>,
+}
+
+impl KirLigandScores {
+    /// Keys an allele can be looked up by, allele specific entries take precedence over its class
+    fn keys(&self, allele: &ClassI, motif: Option<&LigandMotif>) -> Vec<KirLigandKey> {
+        let allele_key = KirLigandKey::Allele(two_field(allele));
+        let mut keys = Vec::new();
+
+        if self.scores.keys().any(|(first, _)| *first == allele_key) {
+            keys.push(allele_key)
+        }
+        if let Some(class) = motif.and_then(KirLigandKey::from_motif) {
+            keys.push(class)
+        }
+
+        keys
+    }
+
+    pub fn score(
+        &self,
+        first: (&ClassI, Option<&LigandMotif>),
+        second: (&ClassI, Option<&LigandMotif>),
+    ) -> Option<f32> {
+        let second_keys = self.keys(second.0, second.1);
+
+        self.keys(first.0, first.1)
+            .into_iter()
+            .find_map(|first_key| {
+                second_keys
+                    .iter()
+                    .find_map(|second_key| {
+                        self.scores.get(&(first_key.clone(), second_key.clone()))
+                    })
+                    .copied()
+            })
+    }
 }
 
 /* LILRB */
@@ -624,33 +1752,99 @@ pub struct LilrbScore {
     pub lilrb2_score: f32,
 }
 
-trait ExhaustiveSearch<'a> {
-    fn get_matching(&self, allele: &ClassI) -> Vec<&'a LilrbScore>;
+/// LILRB similarity scores indexed by allele and allele group of both alleles of every pair
+#[derive(Debug, Default)]
+pub struct LilrbScores {
+    pub scores: Vec<LilrbScore>,
+    by_allele: HashMap<String, Vec<usize>>,
+    by_group: HashMap<String, Vec<usize>>,
 }
 
-impl<'a, I> ExhaustiveSearch<'a> for I
-where
-    I: Iterator<Item = &'a LilrbScore> + Clone,
-{
-    fn get_matching(&self, allele: &ClassI) -> Vec<&'a LilrbScore> {
-        let (allele_group, exact) =
-            self.clone()
-                .fold((Vec::new(), Vec::new()), |(mut group, mut exact), score| {
-                    if score.first_allele.allele_group() == allele.allele_group()
-                        || score.second_allele.allele_group() == allele.allele_group()
-                    {
-                        group.push(score);
-
-                        if score.first_allele == *allele || score.second_allele == *allele {
-                            exact.push(score)
-                        }
-                    }
+impl LilrbScores {
+    pub fn new(scores: Vec<LilrbScore>) -> Self {
+        let mut by_allele = HashMap::<String, Vec<usize>>::new();
+        let mut by_group = HashMap::<String, Vec<usize>>::new();
 
-                    (group, exact)
-                });
+        scores.iter().enumerate().for_each(|(i, score)| {
+            let mut alleles = vec![&score.first_allele, &score.second_allele];
+            alleles.dedup();
+
+            alleles.into_iter().for_each(|allele| {
+                by_allele.entry(allele.to_string()).or_default().push(i);
+            });
+
+            let mut groups = vec![
+                score.first_allele.allele_group(),
+                score.second_allele.allele_group(),
+            ];
+            groups.dedup();
+
+            groups.into_iter().for_each(|group| {
+                by_group.entry(group).or_default().push(i);
+            });
+        });
+
+        Self {
+            scores,
+            by_allele,
+            by_group,
+        }
+    }
+
+    /// Scores including the allele, scores of its allele group when the allele itself is not scored
+    fn candidates(&self, allele: &ClassI) -> &[usize] {
+        self.by_allele
+            .get(&allele.to_string())
+            .or_else(|| self.by_group.get(&allele.allele_group()))
+            .map(|candidates| candidates.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Scores of exactly the index and the allele
+    pub fn exact(&self, index: &ClassI, allele: &ClassI) -> Vec<&LilrbScore> {
+        self.by_allele
+            .get(&index.to_string())
+            .map(|candidates| {
+                candidates
+                    .iter()
+                    .map(|i| &self.scores[*i])
+                    .filter(|score| {
+                        (score.first_allele == *index && score.second_allele == *allele)
+                            || (score.first_allele == *allele && score.second_allele == *index)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Scores between the index and the allele, falling back to the allele groups when either of
+    /// the alleles is not scored
+    pub fn matching(&self, index: &ClassI, allele: &ClassI) -> Vec<&LilrbScore> {
+        let index_scores = self
+            .candidates(index)
+            .iter()
+            .map(|i| &self.scores[*i])
+            .collect::<Vec<&LilrbScore>>();
+
+        let includes = |score: &&LilrbScore, matches: &dyn Fn(&ClassI) -> bool| {
+            matches(&score.first_allele) || matches(&score.second_allele)
+        };
+
+        let exact = index_scores
+            .iter()
+            .filter(|score| includes(score, &|other| other == allele))
+            .copied()
+            .collect::<Vec<&LilrbScore>>();
 
         if exact.is_empty() {
-            allele_group
+            index_scores
+                .into_iter()
+                .filter(|score| {
+                    includes(score, &|other| {
+                        other.allele_group() == allele.allele_group()
+                    })
+                })
+                .collect()
         } else {
             exact
         }
@@ -661,7 +1855,7 @@ where
 mod tests {
     use super::*;
     use crate::io::reader::read_lilrb_scores;
-    use netmhcpan::reader::read_raw_netmhcpan;
+    use crate::test_utils::{binding_data, cohort, kir_reference, motif_sets};
 
     #[test]
     fn test_create_measure() {
@@ -672,28 +1866,632 @@ mod tests {
             measure,
             Measure {
                 name: "CD8".to_string(),
-                motif_pos: vec![2, 3, 4, 5, 6, 9]
+                motif_pos: vec![2, 3, 4, 5, 6, 9],
+                engaging: None,
             }
         )
     }
 
+    #[test]
+    fn test_kir_engagement() {
+        let rules = crate::io::reader::read_kir_peptide_rules();
+        let engagements = rules.engagements();
+        let kir2dl3 = engagements
+            .iter()
+            .find(|engagement| engagement.kir.to_string() == "KIR2DL3")
+            .unwrap();
+
+        assert!(kir2dl3.engages("AAAAAAAAL"));
+        assert!(!kir2dl3.engages("AAAAAAAKL"));
+        assert!(!kir2dl3.engages("AAAAAAEAL"));
+        // p8 of a 9mer is the penultimate residue of a 10mer
+        assert!(!kir2dl3.engages("AAAAAAAAKL"));
+
+        let enabling = KirEngagement {
+            kir: kir2dl3.kir.clone(),
+            rules: vec![PeptideRule {
+                kir: kir2dl3.kir.clone(),
+                position: 8,
+                residues: "AS".to_string(),
+                effect: PeptideEffect::Enable,
+            }],
+        };
+        assert!(enabling.engages("AAAAAAASL"));
+        assert!(!enabling.engages("AAAAAAAGL"));
+
+        let measure = crate::KIR_DEF.parse::<Measure>().unwrap();
+        assert_eq!(measure.kir_engaging(enabling).name, "KIR_KIR2DL3");
+    }
+
     #[test]
     fn test_calculate_fs() {
-        let binding_data =
-            read_raw_netmhcpan(vec!["tests/input/binding_predictions/netmhcpan_wBA.txt"]).unwrap();
-        let comb = create_calc_combs(&binding_data);
+        let binding_data = binding_data();
+        let alleles = binding_data.list_alleles();
+        let n = alleles.len();
+        assert_eq!(allele_pairs(alleles.clone(), alleles).count(), n * (n - 1));
+
+        let measures = vec![crate::KIR_DEF.parse::<Measure>().unwrap()];
+        let motif_sets = motif_sets(BindingWeight::Hard, false);
+        let ligand_map = KirLigandMap::default();
+        let options = FsOptions {
+            pep_lengths: vec![9],
+            bootstrap: Some(Bootstrap::new(100, 1, 0.95).unwrap()),
+            null_model: Some(NullModel::new(20, 1, &binding_data).unwrap()),
+        };
+        let (sender, receiver) = std::sync::mpsc::sync_channel(1);
+
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let alleles = binding_data.list_alleles();
+                calculate_fs(
+                    allele_pairs(alleles.clone(), alleles),
+                    &motif_sets,
+                    &measures,
+                    &ligand_map,
+                    AmbiguityPolicy::First,
+                    &options,
+                    sender,
+                )
+            });
+
+            let results = receiver.iter().collect::<Vec<CalcFsResult>>();
+            assert_eq!(results.len(), n * (n - 1));
+            // Results are written in the order of the pairs
+            let alleles = binding_data.list_alleles();
+            assert!(results
+                .iter()
+                .map(|result| (&result.index, &result.non_index))
+                .eq(allele_pairs(alleles.clone(), alleles)));
+            results
+                .iter()
+                .filter(|result| result.index_bound > 0)
+                .for_each(|result| {
+                    let (lower, upper) = (result.fs_ci_lower.unwrap(), result.fs_ci_upper.unwrap());
+                    assert!(lower <= result.fraction_shared && result.fraction_shared <= upper);
+                    let (expected, p_value) =
+                        (result.expected_fs.unwrap(), result.fs_p_value.unwrap());
+                    assert!((0.0..=1.0).contains(&expected));
+                    assert!(p_value > 0.0 && p_value <= 1.0);
+                });
+        });
+    }
+
+    #[test]
+    fn test_cohort_allele_pairs() {
+        let binding_data = binding_data();
+        let b2705 = "B*27:05".parse::<ClassI>().unwrap();
+        let a0301 = "A*03:01".parse::<ClassI>().unwrap();
+
+        // A*02:01 and C*04:01 have no binding predictions
+        let input = "ID,A1,A2,B1,B2,C1,C2,KIR2DL1,KIR2DS1\n\
+                     cohort,A*03:01,A*02:01,B*27:05,B*27:05,C*04:01,C*04:01,1,0\n";
+        let cohort = cohort(input);
+        let (index, non_index) =
+            cohort_allele_pairs(&binding_data, std::slice::from_ref(&b2705), &cohort);
+        assert_eq!(
+            allele_pairs(index.clone(), non_index.clone()).collect::<Vec<_>>(),
+            vec![(&b2705, &a0301)]
+        );
+
+        let measures = vec![crate::KIR_DEF.parse::<Measure>().unwrap()];
+        let motif_sets = motif_sets(BindingWeight::Hard, false);
+        let ligand_map = KirLigandMap::init().unwrap();
+        let kir_reference = kir_reference();
+        let cohort_fs = |index_alleles: Vec<&ClassI>, non_index_alleles: Vec<&ClassI>| {
+            let (sender, receiver) = std::sync::mpsc::sync_channel(1);
+            let fs_result = std::thread::scope(|scope| {
+                scope.spawn(|| {
+                    calculate_fs(
+                        allele_pairs(index_alleles, non_index_alleles),
+                        &motif_sets,
+                        &measures,
+                        &ligand_map,
+                        AmbiguityPolicy::First,
+                        &FsOptions {
+                            pep_lengths: vec![9],
+                            ..FsOptions::default()
+                        },
+                        sender,
+                    )
+                });
+                receiver.iter().collect::<Vec<CalcFsResult>>()
+            });
+            let index_cache = IndexCache::new(vec![b2705.clone()], fs_result, "NA");
+            calculate_index_cohort_fs(index_cache, &cohort, &kir_reference)
+                .into_iter()
+                .map(|result| (result.fs, result.ikir_fs, result.akir_fs))
+                .collect::<Vec<_>>()
+        };
+
+        let cohort_result = cohort_fs(index, non_index);
+        assert!(!cohort_result.is_empty());
+        assert_eq!(
+            cohort_result,
+            cohort_fs(binding_data.list_alleles(), binding_data.list_alleles())
+        );
+    }
+
+    #[test]
+    fn test_bootstrap() {
+        let bootstrap = Bootstrap::new(1000, 1, 0.9).unwrap();
+        let shared = vec![(1.0, 1.0), (1.0, 0.0), (1.0, 1.0), (1.0, 0.0)];
+
+        let interval = bootstrap.fraction_shared(&shared, 7).unwrap();
+        assert_eq!(bootstrap.fraction_shared(&shared, 7), Some(interval));
+        assert!(interval.lower < 0.5 && interval.upper > 0.5);
+        // Standard error of a proportion of 4 motifs
+        assert!((interval.se - 0.25).abs() < 0.03);
+
+        let all_shared = bootstrap.fraction_shared(&[(1.0, 1.0); 3], 7).unwrap();
+        assert_eq!(
+            (all_shared.lower, all_shared.upper, all_shared.se),
+            (1.0, 1.0, 0.0)
+        );
+        assert_eq!(bootstrap.fraction_shared(&[], 7), None);
+        assert!(Bootstrap::new(1000, 1, 1.5).is_err());
+
+        // Seeds are FNV-1a hashes and must not change between releases
+        assert_eq!(
+            calculation_seed(1, &["B*27:05", "KIR"], 9),
+            0xd1af_d728_6999_2f77
+        );
+    }
+
+    #[test]
+    fn test_shuffled_peptide() {
+        use rand::SeedableRng;
+        use rand_chacha::ChaCha8Rng;
+
+        let sorted = |peptide: &str| {
+            let mut residues = peptide.chars().collect::<Vec<char>>();
+            residues.sort_unstable();
+            residues
+        };
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let shuffled = (0..100)
+            .map(|_| NullModel::shuffled_peptide("GHQAAMQML", None, &mut rng).unwrap())
+            .collect::<HashSet<String>>();
+        assert!(shuffled.len() > 1);
+        assert!(shuffled
+            .iter()
+            .all(|peptide| sorted(peptide) == sorted("GHQAAMQML")));
+    }
 
-        dbg!(&comb);
-        dbg!(&comb.len());
+    #[test]
+    fn test_null_sets() {
+        // A random peptide for only one of two bound peptides, its motif bound by the non index
+        let null_set = NullSet::new(vec![(Some(3), 1.0, 0), (None, 1.0, 1)].into_iter());
+        let non_index = MotifSet {
+            motifs: vec![3, 8],
+            weights: vec![1.0; 2],
+            ..MotifSet::default()
+        };
+        assert_eq!(
+            NullFs::new(&[null_set.clone(), null_set], &non_index, 0.75),
+            Some(NullFs {
+                expected: 0.5,
+                p_value: 1.0 / 3.0
+            })
+        );
+        assert_eq!(NullFs::new(&[], &non_index, 0.75), None);
+
+        let binding_data = binding_data();
+        let measures = vec![crate::KIR_DEF.parse::<Measure>().unwrap()];
+        let motif_sets = motif_sets(BindingWeight::Hard, false);
+        let null_model = NullModel::new(5, 1, &binding_data).unwrap();
+        let index = "B*27:05".parse::<ClassI>().unwrap();
+        let index_null_sets = null_model.index_null_sets(&index, &motif_sets, &measures, &[9]);
+        let null_sets = &index_null_sets[&(measures[0].name.clone(), 9)];
+        assert_eq!(null_sets.len(), 5);
+        assert!(null_sets
+            .iter()
+            .all(|null_set| null_set.weight == motif_sets.get(&index, "KIR", 9).unwrap().weight()));
+        assert!(null_model
+            .index_null_sets(&index, &motif_sets, &measures, &[8])
+            .is_empty());
+    }
+
+    #[test]
+    fn test_motif_set_shared() {
+        let index = MotifSet {
+            motifs: vec![1, 2, 2, 5, 7],
+            weights: vec![1.0; 5],
+            ..MotifSet::default()
+        };
+        let non_index = MotifSet {
+            motifs: vec![2, 3, 7, 7, 9],
+            weights: vec![1.0; 5],
+            ..MotifSet::default()
+        };
+
+        assert_eq!(index.shared_weight(&non_index), 3.0);
+        assert_eq!(non_index.shared_weight(&index), 3.0);
+        assert_eq!(index.fraction_shared(&non_index), 0.6);
+        assert!(MotifSet::default().fraction_shared(&index).is_nan());
+
+        // Soft bound motifs count with their weight and the strongest binding in the other set
+        let soft_index = MotifSet {
+            motifs: vec![2, 5],
+            weights: vec![0.5, 1.0],
+            ..MotifSet::default()
+        };
+        let soft_non_index = MotifSet {
+            motifs: vec![2, 2, 9],
+            weights: vec![0.2, 0.8, 1.0],
+            ..MotifSet::default()
+        };
+        assert_eq!(soft_index.shared_weight(&soft_non_index), 0.5);
+        assert_eq!(soft_non_index.shared_weight(&soft_index), 0.7);
+        assert_eq!(soft_index.fraction_shared(&soft_non_index), 0.5 / 1.5);
+        assert_eq!(soft_non_index.fraction_shared(&soft_non_index), 1.0);
+        assert_eq!(soft_non_index.unique_weight(), 1.8);
+        assert_eq!(soft_non_index.unique_shared_weight(&soft_index), 0.5);
+        assert_eq!(index.unique_weight(), 4.0);
+        assert_eq!(index.unique_shared_weight(&non_index), 2.0);
+    }
+
+    #[test]
+    fn test_overlap_metrics() {
+        let allele = "A*01:01".parse::<ClassI>().unwrap();
+        let result = CalcFsResult {
+            measure: "KIR".to_string(),
+            index: allele.clone(),
+            non_index: allele,
+            index_ligand_motif: None,
+            non_index_ligand_motif: None,
+            fraction_shared: 0.6,
+            non_index_fraction_shared: None,
+            jaccard: None,
+            dice: None,
+            overlap_coefficient: None,
+            shared_motifs: None,
+            fs_ci_lower: None,
+            fs_ci_upper: None,
+            fs_se: None,
+            expected_fs: None,
+            fs_p_value: None,
+            peptide_length: 9,
+            threshold: BindingCutoff::Rank(2.0),
+            non_index_threshold: BindingCutoff::Rank(2.0),
+            index_bound: 5,
+            non_index_bound: 3,
+            ligand_table: "NA".to_string(),
+            non_index_weight: 3.0,
+            non_index_shared: 3.0,
+            unique_index_weight: 5.0,
+            unique_non_index_weight: 3.0,
+            unique_shared: 3.0,
+        };
+
+        let metrics = ["non-index-fraction", "jaccard", "dice", "overlap", "shared"]
+            .iter()
+            .map(|metric| metric.parse::<OverlapMetric>().unwrap())
+            .collect::<Vec<OverlapMetric>>();
+        let with_metrics = result.clone().with_metrics(&metrics);
+        assert_eq!(with_metrics.non_index_fraction_shared, Some(1.0));
+        assert_eq!(with_metrics.jaccard, Some(0.6));
+        assert_eq!(with_metrics.dice, Some(0.75));
+        assert_eq!(with_metrics.overlap_coefficient, Some(1.0));
+        assert_eq!(with_metrics.shared_motifs, Some(3.0));
+
+        let jaccard_only = result.clone().with_metrics(&[OverlapMetric::Jaccard]);
+        assert_eq!(jaccard_only.dice, None);
+        assert!("cosine".parse::<OverlapMetric>().is_err());
+
+        // Duplicated motifs count once in the symmetric metrics
+        let index = MotifSet {
+            motifs: vec![4, 4, 4],
+            weights: vec![1.0; 3],
+            ..MotifSet::default()
+        };
+        let non_index = MotifSet {
+            motifs: vec![4],
+            weights: vec![1.0],
+            ..MotifSet::default()
+        };
+        let duplicated = CalcFsResult {
+            fraction_shared: index.fraction_shared(&non_index),
+            non_index_weight: non_index.weight(),
+            non_index_shared: non_index.shared_weight(&index),
+            unique_index_weight: index.unique_weight(),
+            unique_non_index_weight: non_index.unique_weight(),
+            unique_shared: index.unique_shared_weight(&non_index),
+            ..result
+        }
+        .with_metrics(&metrics);
+        assert_eq!(duplicated.fraction_shared, 1.0);
+        assert_eq!(duplicated.non_index_fraction_shared, Some(1.0));
+        assert_eq!(duplicated.jaccard, Some(1.0));
+        assert_eq!(duplicated.dice, Some(1.0));
+        assert_eq!(duplicated.overlap_coefficient, Some(1.0));
+        assert_eq!(duplicated.shared_motifs, Some(1.0));
+    }
+
+    #[test]
+    fn test_motif_sets() {
+        let hard_sets = motif_sets(BindingWeight::Hard, false);
+        let unique_sets = motif_sets(BindingWeight::Hard, true);
+        let allele = "B*27:05".parse::<ClassI>().unwrap();
+
+        let motifs = hard_sets.get(&allele, "KIR", 9).unwrap();
+        let unique = unique_sets.get(&allele, "KIR", 9).unwrap();
+        assert!(!motifs.is_empty());
+        assert!(unique.motifs.len() <= motifs.motifs.len());
+        assert_eq!(motifs.fraction_shared(motifs), 1.0);
+        assert!(hard_sets.get(&allele, "TCR", 9).is_none());
+
+        let linear_sets = motif_sets(BindingWeight::Linear, false);
+        let linear = linear_sets.get(&allele, "KIR", 9).unwrap();
+        assert_eq!(linear.motifs.len(), motifs.motifs.len());
+        assert!(linear.weight() < motifs.weight());
+        assert_eq!(linear.fraction_shared(linear), 1.0);
+
+        // Peptides beyond the cutoff keep a small logistic weight, only those weighted at least 0.5
+        // count as bound and those below the minimum weight are left out
+        let logistic_sets = motif_sets(BindingWeight::Logistic, false);
+        let logistic = logistic_sets.get(&allele, "KIR", 9).unwrap();
+        assert!(logistic.motifs.len() >= motifs.motifs.len());
+        assert!(logistic
+            .weights
+            .iter()
+            .all(|weight| *weight >= BindingWeight::MIN_WEIGHT));
+        assert_eq!(logistic.bound_count(), motifs.bound_count());
+        assert_eq!(motifs.bound_count(), motifs.motifs.len());
+
+        // Motif hashes are FNV-1a hashes and must not change between releases
+        assert_eq!(motif_hash("ABC", &[0, 2]), 0xfa33_4e19_a077_3046);
+    }
+
+    #[test]
+    fn test_threshold_motif_sets() {
+        let binding_data = binding_data();
+        let measures = vec![crate::KIR_DEF.parse::<Measure>().unwrap()];
+        let all_alleles = binding_data.list_alleles().into_iter().collect();
+        let hashed_motifs = HashedMotifs::new(&binding_data, &all_alleles, &measures, &[9]);
+        let allele = "B*27:05".parse::<ClassI>().unwrap();
+        assert!(hashed_motifs.has_affinity());
+        assert!(!HashedMotifs::default().has_affinity());
+
+        let cutoffs = ["0.5", "strong", "weak", "50000nM"]
+            .iter()
+            .map(|threshold| {
+                threshold
+                    .parse::<BindingThreshold>()
+                    .unwrap()
+                    .cutoff(&binding_data)
+            })
+            .collect::<Vec<BindingCutoff>>();
+        assert_eq!(cutoffs[1], BindingCutoff::Rank(0.5));
+        assert_eq!(cutoffs[2], BindingCutoff::Rank(2.0));
+        assert_eq!(cutoffs[3].to_string(), "50000nM");
+
+        let bound = cutoffs
+            .iter()
+            .map(|cutoff| {
+                let motif_sets =
+                    hashed_motifs.motif_sets((&(*cutoff).into(), BindingWeight::Hard), false);
+                assert_eq!(motif_sets.cutoff(&allele), *cutoff);
+                motif_sets.get(&allele, "KIR", 9).unwrap().motifs.len()
+            })
+            .collect::<Vec<usize>>();
+        assert!(bound[0] <= bound[2]);
+        assert!(bound[3] > 0);
+
+        // Alleles with their own cutoff override the default
+        let allele_cutoffs = AlleleCutoffs::new(
+            BindingCutoff::Rank(0.5),
+            vec![(allele.clone(), BindingCutoff::Rank(2.0))]
+                .into_iter()
+                .collect(),
+        );
+        let motif_sets = hashed_motifs.motif_sets((&allele_cutoffs, BindingWeight::Hard), false);
+        assert_eq!(
+            motif_sets.get(&allele, "KIR", 9).unwrap().motifs.len(),
+            bound[2]
+        );
+        assert!("0".parse::<BindingThreshold>().is_err());
+        assert!("-5nM".parse::<BindingThreshold>().is_err());
+        assert!("medium".parse::<BindingThreshold>().is_err());
+    }
+
+    /// FS result of the pair calculated by `calculate_fs` from hard bound motifs of the KIR measure
+    fn pair_fs_result(
+        (index, index_motifs): (&ClassI, Vec<u64>),
+        (non_index, non_index_motifs): (&ClassI, Vec<u64>),
+    ) -> CalcFsResult {
+        let measures = vec![crate::KIR_DEF.parse::<Measure>().unwrap()];
+        let motif_set = |allele: &ClassI, motifs: Vec<u64>| {
+            let key = (allele.clone(), measures[0].name.to_string(), 9);
+            let motif_set = MotifSet {
+                weights: vec![1.0; motifs.len()],
+                motifs,
+                ..MotifSet::default()
+            };
+            (key, motif_set)
+        };
+        let motif_sets = MotifSets {
+            sets: vec![
+                motif_set(index, index_motifs),
+                motif_set(non_index, non_index_motifs),
+            ]
+            .into_iter()
+            .collect(),
+            cutoffs: BindingCutoff::Rank(2.0).into(),
+        };
+        let options = FsOptions {
+            pep_lengths: vec![9],
+            bootstrap: None,
+            null_model: None,
+        };
+        let (sender, receiver) = std::sync::mpsc::sync_channel(1);
+
+        calculate_fs(
+            std::iter::once((index, non_index)),
+            &motif_sets,
+            &measures,
+            &KirLigandMap::init().unwrap(),
+            AmbiguityPolicy::First,
+            &options,
+            sender,
+        );
+        receiver.recv().unwrap()
+    }
+
+    #[test]
+    fn test_kir_aware_fs() {
+        let index = "C*06:02".parse::<ClassI>().unwrap();
+        let c2 = "C*04:01".parse::<ClassI>().unwrap();
+        let kir_reference = kir_reference();
+        let index_cache = IndexCache::new(
+            vec![index.clone()],
+            vec![pair_fs_result(
+                (&index, vec![1, 2, 3, 4]),
+                (&c2, vec![1, 2, 5, 6]),
+            )],
+            "NA",
+        );
+
+        // Individuals with only an inhibitory or only an activating C2 receptor, the copies are a
+        // covariate and do not change the FS
+        let input = "ID,A1,A2,B1,B2,C1,C2,KIR2DL1,KIR2DS1\n\
+                     inhibitory,A*01:01,A*02:01,B*57:01,B*08:01,C*04:01,C*04:01,1,0\n\
+                     activating,A*01:01,A*02:01,B*57:01,B*08:01,C*04:01,C*04:01,0,1\n\
+                     two_copies,A*01:01,A*02:01,B*57:01,B*08:01,C*04:01,C*04:01,2,0\n";
+        let cohort = cohort(input);
+        let results = calculate_index_cohort_fs(index_cache, &cohort, &kir_reference);
+        let kir_fs = |id: &str| {
+            let result = results.iter().find(|result| result.id == id).unwrap();
+            (
+                result.ikir_fs,
+                result.akir_fs,
+                result.ikir_copies,
+                result.akir_copies,
+            )
+        };
+
+        assert_eq!(
+            kir_fs("inhibitory"),
+            (Some(0.5), Some(0.0), Some(1), Some(0))
+        );
+        assert_eq!(
+            kir_fs("activating"),
+            (Some(0.0), Some(0.5), Some(0), Some(1))
+        );
+        assert_eq!(
+            kir_fs("two_copies"),
+            (Some(0.5), Some(0.0), Some(2), Some(0))
+        );
+    }
+
+    #[test]
+    fn test_kir_weighted_fs() {
+        use crate::io::reader::{read_kir_allotypes, read_kir_ligand_scores};
+
+        let index = "C*06:02".parse::<ClassI>().unwrap();
+        let c2 = "C*04:01".parse::<ClassI>().unwrap();
+        let mut binding = KirBindingTable::default();
+        binding.insert("KIR2DL1".parse().unwrap(), LigandMotif::C2, 0.5);
+        binding.insert("KIR2DS1".parse().unwrap(), LigandMotif::C2, 0.25);
+        let kir_reference =
+            KirReference::new(binding, read_kir_allotypes(), read_kir_ligand_scores());
+        let index_cache = IndexCache::new(
+            vec![index.clone()],
+            vec![pair_fs_result(
+                (&index, vec![1, 2, 3, 4]),
+                (&c2, vec![1, 2, 5, 6]),
+            )],
+            "NA",
+        );
+
+        let input = "ID,A1,A2,B1,B2,C1,C2,KIR2DL1,KIR2DS1\n\
+                     inhibitory,A*01:01,A*02:01,B*57:01,B*08:01,C*04:01,C*04:01,1,0\n\
+                     activating,A*01:01,A*02:01,B*57:01,B*08:01,C*04:01,C*04:01,0,1\n\
+                     both,A*01:01,A*02:01,B*57:01,B*08:01,C*04:01,C*04:01,1,1\n";
+        let cohort = cohort(input);
+        let results = calculate_index_cohort_fs(index_cache, &cohort, &kir_reference);
+        let weighted_fs = |id: &str| {
+            let result = results.iter().find(|result| result.id == id).unwrap();
+            (result.ikir_weighted_fs, result.akir_weighted_fs)
+        };
+
+        // The KIR-aware FS of 0.5 is scaled by the strength of the shared receptor
+        assert_eq!(weighted_fs("inhibitory"), (Some(0.25), Some(0.0)));
+        assert_eq!(weighted_fs("activating"), (Some(0.0), Some(0.125)));
+        assert_eq!(weighted_fs("both"), (Some(0.25), Some(0.125)));
+    }
+
+    #[test]
+    fn test_lilrb_without_fs() {
+        use crate::receptor::{calculate_lilrb_scores, LilrbOptions, LilrbReceptor};
+
+        // No FS is calculated for the index allele, e.g. without binding predictions for it
+        let index = "A*01:01".parse::<ClassI>().unwrap();
+        let index_cache = IndexCache::new(vec![index.clone()], Vec::new(), "NA");
+        let kir_reference = kir_reference();
+
+        let input = "ID,A1,A2,B1,B2,C1,C2,KIR2DL1,KIR2DS1\n\
+                     missing_fs,A*02:01,A*02:01,B*57:01,B*08:01,C*04:01,C*04:01,1,0\n";
+        let cohort = cohort(input);
+        let cohort_result = calculate_index_cohort_fs(index_cache, &cohort, &kir_reference);
+        assert!(cohort_result.iter().all(|result| result.fs.is_none()));
+
+        let lilrb_receptors =
+            LilrbReceptor::from_scores(read_lilrb_scores(), LilrbOptions::default());
+        let lilrb_result = calculate_lilrb_scores(&[index], &cohort, &lilrb_receptors, "NA");
+        assert_eq!(lilrb_result.len(), 1);
+        assert_eq!(lilrb_result[0].id, "missing_fs");
+        assert!(lilrb_result[0].lilrb1.is_some());
+        assert!(lilrb_result[0].lilrb2.is_some());
+        assert_eq!(lilrb_result[0].lilrb1_fallback, "exact");
+    }
+
+    #[test]
+    fn test_lilrb_matching() {
+        let allele = |allele: &str| allele.parse::<ClassI>().unwrap();
+        let score = |first: &str, second: &str, lilrb1_score| LilrbScore {
+            first_allele: allele(first),
+            second_allele: allele(second),
+            lilrb1_score,
+            lilrb2_score: lilrb1_score,
+        };
+        let lilrb_scores = LilrbScores::new(vec![
+            score("A*01:01", "A*03:01", 0.1),
+            score("A*01:01", "A*03:02", 0.2),
+            score("A*03:03", "A*01:01", 0.3),
+            score("B*07:02", "A*03:01", 0.4),
+        ]);
+        let matched = |index: &str, other: &str| {
+            lilrb_scores
+                .matching(&allele(index), &allele(other))
+                .iter()
+                .map(|score| score.lilrb1_score)
+                .collect::<Vec<f32>>()
+        };
+
+        // Listed pairs are used as they are, in either order
+        assert_eq!(matched("A*01:01", "A*03:02"), vec![0.2]);
+        assert_eq!(matched("A*01:01", "A*03:03"), vec![0.3]);
+        // Alleles that are not scored fall back to all scores of their allele group
+        assert_eq!(matched("A*01:01", "A*03:04"), vec![0.1, 0.2, 0.3]);
+        assert_eq!(matched("B*07:05", "A*03:01"), vec![0.4]);
+        assert_eq!(matched("B*07:05", "A*03:04"), vec![0.4]);
+        assert!(matched("A*01:01", "B*07:02").is_empty());
+        assert!(matched("C*01:02", "A*03:01").is_empty());
     }
 
     #[test]
     fn test_lilrb_search() {
         let lilrb_scores = read_lilrb_scores();
-        let test_allele = "A*03:02".parse::<ClassI>().unwrap();
-        let ref_scores = lilrb_scores.iter().collect::<Vec<&LilrbScore>>();
-
-        dbg!(lilrb_scores.iter().get_matching(&test_allele));
-        dbg!(ref_scores.iter().copied().get_matching(&test_allele));
+        let a0101 = "A*01:01".parse::<ClassI>().unwrap();
+        let a0201 = "A*02:01".parse::<ClassI>().unwrap();
+        let a0302 = "A*03:02".parse::<ClassI>().unwrap();
+
+        assert_eq!(lilrb_scores.matching(&a0101, &a0201).len(), 1);
+        // A*03:02 is not scored so all A*03 scores are used
+        let group_scores = lilrb_scores.matching(&a0101, &a0302);
+        assert!(!group_scores.is_empty());
+        assert!(group_scores
+            .iter()
+            .all(|score| score.second_allele.allele_group() == "A03"));
     }
 }
@@ -1,15 +1,23 @@
 const PEPTIDE_LENGTHS: [&str; 4] = ["8", "9", "10", "11"];
+const AMBIGUITY_POLICIES: [&str; 4] = ["first", "majority", "common", "ambiguous"];
+const LILRB_AGGREGATIONS: [&str; 4] = ["max", "mean", "sum", "index-excluded"];
+const LILRB_FALLBACKS: [&str; 3] = ["exact", "allele-group", "nearest"];
+const BINDING_WEIGHTS: [&str; 4] = ["hard", "logistic", "linear", "affinity"];
+const OVERLAP_METRICS: [&str; 5] = ["non-index-fraction", "jaccard", "dice", "overlap", "shared"];
 
 use std::path::PathBuf;
+use structopt::clap::AppSettings;
 use structopt::StructOpt;
 
-use crate::calc::Measure;
+use crate::calc::{BindingThreshold, BindingWeight, KirPeptideRules, Measure, OverlapMetric};
 use crate::error::Error;
-use crate::io::reader::*;
 use crate::io::writer::*;
-use crate::{KIR_DEF, LOGGING_MODULES, PROJECT_LIGAND_TABLE, TCR_DEF};
+use crate::ligand::{diff_ligand_maps, LigandStore, LEGACY_VERSION};
+use crate::receptor::{LilrbAggregation, LilrbFallback};
+use crate::shard::{merge_shards, Shard, SHARD_INFO_FILE};
+use crate::{KIR_DEF, LOGGING_MODULES, TCR_DEF};
 
-use immunoprot::ig_like::kir_ligand::KirLigandMap;
+use immunoprot::ig_like::kir_ligand::{AmbiguityPolicy, HttpIpdSource, KirLigandMap};
 use immunoprot::mhc::hla::ClassI;
 use log::{info, warn};
 
@@ -17,7 +25,8 @@ use log::{info, warn};
 #[structopt(
     name = "fstool",
     about = "Calculates fraction of shared bound motifs between HLA alleles while incorporating KIR ligand and LILRB binding information.",
-    rename_all = "kebab-case"
+    rename_all = "kebab-case",
+    setting = AppSettings::SubcommandsNegateReqs
 )]
 pub struct Opt {
     /// Number of threads
@@ -32,9 +41,23 @@ pub struct Opt {
     #[structopt(long)]
     /// Updates the current kir ligand group data
     pub update: bool,
+    #[structopt(long, requires = "update")]
+    /// Base URL of the IPD-KIR ligand service used by `--update` (the locus is appended to it)
+    pub ipd_url: Option<String>,
+    #[structopt(long, parse(from_os_str), conflicts_with = "update")]
+    /// Updates the kir ligand group data offline from saved IPD-KIR files (HTML page, TSV export or JSON dump)
+    pub update_from: Vec<PathBuf>,
+    #[structopt(long)]
+    /// Name of the kir ligand table version created by an update (defaults to the current date)
+    pub ligand_name: Option<String>,
+    #[structopt(long)]
+    /// Kir ligand table version to use for this run instead of the active one (see `ligand list`)
+    pub ligand_table: Option<String>,
     #[structopt(long)]
     /// Lists default measure names and motif positions as well as the default location
     /// updated kir ligand will be stored
+    // Read from the clap matches before the options are parsed, as is the subcommand
+    #[allow(dead_code)]
     pub settings: bool,
     #[structopt(short, long, required_unless = "settings")]
     /// Directory to store outputs
@@ -51,9 +74,54 @@ pub struct Opt {
     #[structopt(short, long, possible_values = &PEPTIDE_LENGTHS, default_value = "9")]
     /// Which length of input peptide sequence to consider
     pub peptide_length: Vec<usize>,
+    /// How much each peptide counts as bound, `hard` counts peptides below the rank threshold while
+    /// the soft weights use the %rank (`logistic`, `linear`) or the predicted affinity (`affinity`,
+    /// independent of the binding threshold and not allowed with `--threshold-sweep`)
+    #[structopt(long, possible_values = &BINDING_WEIGHTS, default_value = "hard")]
+    pub binding_weight: BindingWeight,
+    /// Binding criterion, the `weak` or `strong` %rank threshold of the predictions, a %rank (e.g.
+    /// 1.5) or an affinity (e.g. 500nM)
+    #[structopt(long, default_value = "weak")]
+    pub binding_threshold: BindingThreshold,
+    /// Tab separated file of alleles and their binding threshold, other alleles use
+    /// `--binding-threshold`
+    #[structopt(long, parse(from_os_str))]
+    pub allele_thresholds: Option<PathBuf>,
+    /// Reruns the FS (and cohort) calculations for each binding threshold (comma separated, same
+    /// format as `--binding-threshold`), results are written with a threshold column
+    #[structopt(long, use_delimiter = true, conflicts_with_all = &["binding-threshold", "allele-thresholds"])]
+    pub threshold_sweep: Vec<BindingThreshold>,
+    /// Number of bootstrap replicates of the index allele's bound motifs used for confidence
+    /// intervals and standard errors of the fraction shared
+    #[structopt(long)]
+    pub bootstrap: Option<usize>,
+    /// Coverage of the bootstrap percentile confidence intervals
+    #[structopt(long, default_value = "0.95")]
+    pub confidence_level: f32,
+    /// Number of null model replicates, each replacing the index allele's bound peptides by random
+    /// permutations of their residues (same length and amino acid composition, keeping the binding
+    /// weight of the replaced peptide), for the expected FS and an empirical p-value of the
+    /// fraction shared
+    #[structopt(long)]
+    pub null_replicates: Option<usize>,
+    /// Seed of the random number generator used for resampling and null model peptides
+    #[structopt(long, default_value = "1")]
+    pub seed: u64,
+    /// Additional overlap metrics to report next to the fraction of index motifs shared (fraction of
+    /// non index motifs shared, Jaccard, Sørensen–Dice, overlap coefficient or the shared count)
+    #[structopt(long, possible_values = &OVERLAP_METRICS)]
+    pub overlap_metric: Vec<OverlapMetric>,
     /// Custom motif positions to use for calculations (format `Name:index,index..` e.g. KIR:2,7,8,9)
     #[structopt(short, long)]
     pub measure: Option<Vec<Measure>>,
+    /// Adds a measure per receptor in the kir peptide rules using the KIR motif positions and only the
+    /// peptides engaging the receptor
+    #[structopt(long)]
+    pub kir_engaging: bool,
+    /// Tab separated kir peptide rules (receptor, 9mer position, residues, `enable`/`abolish`) to use
+    /// instead of the bundled rules
+    #[structopt(long, parse(from_os_str), requires = "kir-engaging")]
+    pub kir_peptide_rules: Option<PathBuf>,
     /// Whether only unique peptide/motif sequences should be considered in the calculations
     #[structopt(short, long)]
     pub unique: bool,
@@ -63,48 +131,208 @@ pub struct Opt {
     /// Cohort of individuals for which all measures will be calculated
     #[structopt(short, long, requires = "index")]
     pub cohort: Option<PathBuf>,
+    /// Only calculates FS between the index alleles and the alleles present in the cohort instead of
+    /// between all alleles in the binding predictions
+    #[structopt(long, requires = "index")]
+    pub cohort_pairs: bool,
+    /// Only calculates the `i`th of `n` parts of the allele pairs (e.g. 1/4), shard outputs are
+    /// combined with the `merge` command
+    #[structopt(long, conflicts_with = "index")]
+    pub shard: Option<Shard>,
+    /// Does not write the allele FS results (`allele_fs_result.csv`)
+    #[structopt(long)]
+    pub skip_allele_fs: bool,
+    /// Tab separated table of KIR, ';' separated ligand motifs, an optional interaction strength and
+    /// its source to use instead of the bundled KIR ligand motif binding table
+    #[structopt(long, parse(from_os_str), requires = "cohort")]
+    pub kir_binding: Option<PathBuf>,
+    /// Tab separated tables of receptor, ligand (allele, allele group or kir ligand motif) and weight,
+    /// the score of every receptor is reported per individual and index allele
+    #[structopt(long, parse(from_os_str), requires = "cohort")]
+    pub receptor_table: Vec<PathBuf>,
+    /// LILRB similarity table (first allele, second allele, LILRB1 and LILRB2 scores) to use instead
+    /// of the bundled table
+    #[structopt(long, parse(from_os_str), requires = "cohort")]
+    pub lilrb_table: Option<PathBuf>,
+    /// How the LILRB scores of an individual's alleles are combined (`index-excluded` is the maximum
+    /// ignoring alleles identical to the index allele)
+    #[structopt(long, possible_values = &LILRB_AGGREGATIONS, default_value = "index-excluded")]
+    pub lilrb_aggregation: LilrbAggregation,
+    /// Lookup used for allele pairs missing from the LILRB table (allele group average, NetMHCpan
+    /// nearest neighbour or exact pairs only)
+    #[structopt(long, possible_values = &LILRB_FALLBACKS, default_value = "allele-group")]
+    pub lilrb_fallback: LilrbFallback,
+    /// Does not calculate or write the LILRB scores (`lilrb_result.csv`)
+    #[structopt(long)]
+    pub skip_lilrb: bool,
+    /// How to assign a ligand motif when an allele matches several kir ligand alleles with different
+    /// motifs (first after sorting, majority motif, majority among common alleles or leave unassigned)
+    #[structopt(long, possible_values = &AMBIGUITY_POLICIES, default_value = "first")]
+    pub ligand_ambiguity: AmbiguityPolicy,
+    #[structopt(subcommand)]
+    #[allow(dead_code)]
+    pub command: Option<Command>,
 }
 
-impl Opt {
-    pub fn set_logging(&self) {
-        use log::LevelFilter::{self, *};
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub enum Command {
+    /// Manages the stored kir ligand table versions
+    Ligand(LigandCommand),
+    /// Merges the outputs of all shards of a `--shard` run after checking they match
+    Merge {
+        /// Directory to store the merged outputs
+        #[structopt(short, long, parse(from_os_str))]
+        output: PathBuf,
+        /// Prefix to assign to the merged outputs
+        #[structopt(long)]
+        prefix: Option<String>,
+        /// Output directories of the shards
+        #[structopt(parse(from_os_str), required = true)]
+        shards: Vec<PathBuf>,
+    },
+}
 
-        let log_level = if self.quiet {
-            LevelFilter::Error
-        } else {
-            match self.verbose {
-                level if level == 1 => Info,
-                level if level == 2 => Debug,
-                level if level > 2 => Trace,
-                _ => Warn,
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub enum LigandCommand {
+    /// Lists the stored kir ligand table versions, the active version is marked with '*'
+    List,
+    /// Sets the kir ligand table version used by default in subsequent runs
+    Use {
+        /// Name of the version as shown by `ligand list`
+        version: String,
+    },
+    /// Lists alleles whose ligand motif differs between two kir ligand table versions
+    Diff {
+        /// Version to compare from
+        from: String,
+        /// Version to compare to
+        to: String,
+    },
+    /// Makes a kir ligand table updated before versions were kept the active version
+    Migrate,
+}
+
+impl Command {
+    pub fn run(&self) -> Result<(), Error> {
+        match self {
+            Command::Ligand(command) => command.run(),
+            Command::Merge {
+                output,
+                prefix,
+                shards,
+            } => {
+                let prefix = match prefix {
+                    Some(prefix) => format!("{}_", prefix),
+                    _ => String::new(),
+                };
+                merge_shards(shards, output, &prefix)
             }
-        };
+        }
+    }
+}
+
+impl LigandCommand {
+    pub fn run(&self) -> Result<(), Error> {
+        let store = LigandStore::open()?;
+
+        match self {
+            LigandCommand::List => {
+                let active = store.active();
+                store.versions().iter().for_each(|version| {
+                    let marker = if version.name == active { "*" } else { " " };
+                    println!("{} {}\t{}", marker, version.name, version.provenance)
+                });
+            }
+            LigandCommand::Use { version } => {
+                store.set_active(version)?;
+                println!("Using kir ligand table version '{}'", version);
+            }
+            LigandCommand::Diff { from, to } => {
+                let diff = diff_ligand_maps(&store.load(from)?, &store.load(to)?);
+                info!(
+                    "{} alleles differ between versions '{}' and '{}'",
+                    diff.len(),
+                    from,
+                    to
+                );
+                write_ligand_diff(&diff, std::io::stdout())?;
+            }
+            LigandCommand::Migrate => {
+                if store.migrate_legacy()? {
+                    println!("Using kir ligand table version '{}'", LEGACY_VERSION);
+                } else {
+                    println!("No kir ligand table to migrate");
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
 
-        env_logger::builder()
-            .format_module_path(false)
-            .filter_module(LOGGING_MODULES[0], log_level)
-            .filter_module(LOGGING_MODULES[1], log_level)
-            .filter_module(LOGGING_MODULES[2], log_level)
-            .init()
+impl Opt {
+    pub fn set_logging(&self) {
+        set_logging(self.quiet, self.verbose as u64)
     }
 
     pub fn setup_kir_ligand_info(&self) -> Result<KirLigandMap, crate::error::Error> {
+        let store = LigandStore::open()?;
+        let ligand_name = self.ligand_name.as_deref();
+
         if self.update {
             info!("Updating kir ligand information");
-            let update = KirLigandMap::updated().ok();
+            let source = match &self.ipd_url {
+                Some(url) => HttpIpdSource::new(url),
+                _ => HttpIpdSource::default(),
+            };
+            let update = KirLigandMap::updated_from(&source).ok();
             match update {
                 Some(updated_info) => {
-                    write_project_ligand_info(&updated_info);
+                    let version = store.save(&updated_info, ligand_name, source.base_url())?;
+                    store.set_active(&version)?;
                 } ,
                 _ => warn!("Failed to obtain updated kir ligand information from IPD/EBI.\n Trying to use the most recent downloaded version...")
             }
         }
-        let kir_ligand_map = read_project_ligand_info();
 
-        match kir_ligand_map {
-            Some(ligand_map) => Ok(ligand_map),
-            _ => Err(crate::error::Error::KirLigandMapError),
+        if !self.update_from.is_empty() {
+            info!("Updating kir ligand information from local files");
+            let mut updated_info = KirLigandMap::new();
+
+            for path in &self.update_from {
+                let local_info = KirLigandMap::from_local_file(path)
+                    .map_err(|e| Error::CouldNotReadLigandFile(path.clone(), e))?;
+                local_info
+                    .into_infos()
+                    .for_each(|info| updated_info.insert_info(info));
+            }
+
+            let sources = self
+                .update_from
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<String>>()
+                .join(", ");
+            let version = store.save(&updated_info, ligand_name, &sources)?;
+            store.set_active(&version)?;
+        }
+
+        if store.has_legacy() {
+            warn!(
+                "A kir ligand table updated before versions were kept is stored as version '{}', run `ligand migrate` to keep using it by default",
+                LEGACY_VERSION
+            );
         }
+
+        let version = match &self.ligand_table {
+            Some(version) => version.to_string(),
+            _ => store.active(),
+        };
+        info!("Using kir ligand table version '{}'", version);
+
+        store.load(&version)
     }
 
     pub fn output_writers(&self) -> std::result::Result<OutputWriters, Error> {
@@ -116,31 +344,48 @@ impl Opt {
         let output_dir = &self.output;
 
         if !output_dir.exists() {
-            std::fs::create_dir_all(output_dir).or_else(|_| Err(Error::CouldNotCreateOutputDir))?
+            std::fs::create_dir_all(output_dir).map_err(|_| Error::CouldNotCreateOutputDir)?
         }
 
         let allele_path = output_dir.join(format!("{}allele_metadata.csv", prefix));
         let binding_path = output_dir.join(format!("{}allele_binding_summary.csv", prefix));
         let allele_fs_path = output_dir.join(format!("{}allele_fs_result.csv", prefix));
         let cohort_result_path = output_dir.join(format!("{}cohort_result.csv", prefix));
+        let ambiguous_ligands_path = output_dir.join(format!("{}ambiguous_ligands.csv", prefix));
+        let nk_education_path = output_dir.join(format!("{}nk_education.csv", prefix));
+        let receptor_result_path = output_dir.join(format!("{}receptor_result.csv", prefix));
+        let lilrb_result_path = output_dir.join(format!("{}lilrb_result.csv", prefix));
+        let shard_info_path = output_dir.join(format!("{}{}", prefix, SHARD_INFO_FILE));
 
         let allele_meta = csv::WriterBuilder::new()
             .has_headers(true)
             .delimiter(crate::DEFAULT_DELIM)
             .from_path(allele_path)
-            .or_else(|_| Err(Error::CouldNotCreateOutputFile))?;
+            .map_err(|_| Error::CouldNotCreateOutputFile)?;
 
         let binding_meta = csv::WriterBuilder::new()
             .has_headers(true)
             .delimiter(crate::DEFAULT_DELIM)
             .from_path(binding_path)
-            .or_else(|_| Err(Error::CouldNotCreateOutputFile))?;
+            .map_err(|_| Error::CouldNotCreateOutputFile)?;
+
+        let allele_fs_result = if self.skip_allele_fs {
+            None
+        } else {
+            Some(
+                csv::WriterBuilder::new()
+                    .has_headers(true)
+                    .delimiter(crate::DEFAULT_DELIM)
+                    .from_path(allele_fs_path)
+                    .map_err(|_| Error::CouldNotCreateOutputFile)?,
+            )
+        };
 
-        let allele_fs_result = csv::WriterBuilder::new()
+        let ambiguous_ligands = csv::WriterBuilder::new()
             .has_headers(true)
             .delimiter(crate::DEFAULT_DELIM)
-            .from_path(allele_fs_path)
-            .or_else(|_| Err(Error::CouldNotCreateOutputFile))?;
+            .from_path(ambiguous_ligands_path)
+            .map_err(|_| Error::CouldNotCreateOutputFile)?;
 
         let cohort_result = match self.cohort {
             Some(_) => Some(
@@ -148,7 +393,51 @@ impl Opt {
                     .has_headers(true)
                     .delimiter(crate::DEFAULT_DELIM)
                     .from_path(cohort_result_path)
-                    .or_else(|_| Err(Error::CouldNotCreateOutputFile))?,
+                    .map_err(|_| Error::CouldNotCreateOutputFile)?,
+            ),
+            _ => None,
+        };
+
+        let nk_education = match self.cohort {
+            Some(_) => Some(
+                csv::WriterBuilder::new()
+                    .has_headers(true)
+                    .delimiter(crate::DEFAULT_DELIM)
+                    .from_path(nk_education_path)
+                    .map_err(|_| Error::CouldNotCreateOutputFile)?,
+            ),
+            _ => None,
+        };
+
+        let receptor_result = match self.cohort {
+            Some(_) => Some(
+                csv::WriterBuilder::new()
+                    .has_headers(true)
+                    .delimiter(crate::DEFAULT_DELIM)
+                    .from_path(receptor_result_path)
+                    .map_err(|_| Error::CouldNotCreateOutputFile)?,
+            ),
+            _ => None,
+        };
+
+        let lilrb_result = match self.cohort {
+            Some(_) if !self.skip_lilrb => Some(
+                csv::WriterBuilder::new()
+                    .has_headers(true)
+                    .delimiter(crate::DEFAULT_DELIM)
+                    .from_path(lilrb_result_path)
+                    .map_err(|_| Error::CouldNotCreateOutputFile)?,
+            ),
+            _ => None,
+        };
+
+        let shard_info = match self.shard {
+            Some(_) => Some(
+                csv::WriterBuilder::new()
+                    .has_headers(true)
+                    .delimiter(crate::DEFAULT_DELIM)
+                    .from_path(shard_info_path)
+                    .map_err(|_| Error::CouldNotCreateOutputFile)?,
             ),
             _ => None,
         };
@@ -158,24 +447,51 @@ impl Opt {
             binding_meta,
             allele_fs_result,
             cohort_result,
+            ambiguous_ligands,
+            nk_education,
+            receptor_result,
+            lilrb_result,
+            shard_info,
         })
     }
 }
 
+pub fn set_logging(quiet: bool, verbose: u64) {
+    use log::LevelFilter::{self, *};
+
+    let log_level = if quiet {
+        LevelFilter::Error
+    } else {
+        match verbose {
+            1 => Info,
+            2 => Debug,
+            level if level > 2 => Trace,
+            _ => Warn,
+        }
+    };
+
+    env_logger::builder()
+        .format_module_path(false)
+        .filter_module(LOGGING_MODULES[0], log_level)
+        .filter_module(LOGGING_MODULES[1], log_level)
+        .filter_module(LOGGING_MODULES[2], log_level)
+        .init()
+}
+
 pub fn print_defaults() -> Result<(), Box<dyn std::error::Error>> {
     println!("Default Measures:\n  - {}\n  - {}\n", TCR_DEF, KIR_DEF);
+    println!("Default binding threshold:  weak binder %rank of the predictions\n");
 
-    if let Some(project_dir) = directories::ProjectDirs::from("", "", crate::TOOL_NAME) {
-        let data_file = project_dir.data_dir().join(PROJECT_LIGAND_TABLE);
-        let s = if data_file.exists() {
-            "Data is"
-        } else {
-            "Updated data will be"
-        };
-        println!("{} stored at:  '{}'", s, data_file.display());
-        Ok(())
-    } else {
-        Err("Could not create/or read the global data directory".into())
+    match LigandStore::open() {
+        Ok(store) => {
+            println!(
+                "Kir ligand table versions are stored at:  '{}'",
+                store.dir().display()
+            );
+            println!("Active kir ligand table version:  '{}'", store.active());
+            Ok(())
+        }
+        _ => Err("Could not create/or read the global data directory".into()),
     }
 }
 pub fn get_measures(measures: Option<Vec<Measure>>, drop: bool) -> Vec<Measure> {
@@ -188,3 +504,14 @@ pub fn get_measures(measures: Option<Vec<Measure>>, drop: bool) -> Vec<Measure>
 
     measures
 }
+
+/// KIR engaging measures for every receptor with peptide rules
+pub fn get_kir_engaging_measures(rules: &KirPeptideRules) -> Vec<Measure> {
+    let kir_measure = KIR_DEF.parse::<Measure>().unwrap();
+
+    rules
+        .engagements()
+        .into_iter()
+        .map(|engaging| kir_measure.kir_engaging(engaging))
+        .collect()
+}
@@ -1,5 +1,6 @@
-use immunoprot::ig_like::kir::Kir;
-use immunoprot::ig_like::kir_ligand::{KirLigandMap, LigandMotif};
+use crate::calc::KirBindingTable;
+use immunoprot::ig_like::kir::{Kir, KirAllele, KirExpression, KirVariant};
+use immunoprot::ig_like::kir_ligand::{AmbiguityPolicy, KirLigandMap, LigandMotif};
 use immunoprot::mhc::hla::ClassI;
 use serde::Deserialize;
 use std::collections::HashMap;
@@ -56,124 +57,286 @@ pub struct CohortTemp {
     #[serde(
         alias = "KIR2DL1",
         alias = "2DL1",
-        deserialize_with = "crate::io::ser::optional_bool_deserialize",
+        deserialize_with = "crate::io::ser::optional_kir_typing_deserialize",
         default
     )]
-    pub kir2dl1: Option<bool>,
+    pub kir2dl1: Option<KirTyping>,
     #[serde(
         alias = "KIR2DL2",
         alias = "2DL2",
-        deserialize_with = "crate::io::ser::optional_bool_deserialize",
+        deserialize_with = "crate::io::ser::optional_kir_typing_deserialize",
         default
     )]
-    pub kir2dl2: Option<bool>,
+    pub kir2dl2: Option<KirTyping>,
     #[serde(
         alias = "KIR2DL3",
         alias = "2DL3",
-        deserialize_with = "crate::io::ser::optional_bool_deserialize",
+        deserialize_with = "crate::io::ser::optional_kir_typing_deserialize",
         default
     )]
-    pub kir2dl3: Option<bool>,
+    pub kir2dl3: Option<KirTyping>,
     #[serde(
         alias = "KIR2DL4",
         alias = "2DL4",
-        deserialize_with = "crate::io::ser::optional_bool_deserialize",
+        deserialize_with = "crate::io::ser::optional_kir_typing_deserialize",
         default
     )]
-    pub kir2dl4: Option<bool>,
+    pub kir2dl4: Option<KirTyping>,
     #[serde(
         alias = "KIR2DL5",
         alias = "2DL5",
-        deserialize_with = "crate::io::ser::optional_bool_deserialize",
+        deserialize_with = "crate::io::ser::optional_kir_typing_deserialize",
         default
     )]
-    pub kir2dl5: Option<bool>,
+    pub kir2dl5: Option<KirTyping>,
     #[serde(
         alias = "KIR2DS1",
         alias = "2DS1",
-        deserialize_with = "crate::io::ser::optional_bool_deserialize",
+        deserialize_with = "crate::io::ser::optional_kir_typing_deserialize",
         default
     )]
-    pub kir2ds1: Option<bool>,
+    pub kir2ds1: Option<KirTyping>,
     #[serde(
         alias = "KIR2DS2",
         alias = "2DS2",
-        deserialize_with = "crate::io::ser::optional_bool_deserialize",
+        deserialize_with = "crate::io::ser::optional_kir_typing_deserialize",
         default
     )]
-    pub kir2ds2: Option<bool>,
+    pub kir2ds2: Option<KirTyping>,
     #[serde(
         alias = "KIR2DS3",
         alias = "2DS3",
-        deserialize_with = "crate::io::ser::optional_bool_deserialize",
+        deserialize_with = "crate::io::ser::optional_kir_typing_deserialize",
         default
     )]
-    pub kir2ds3: Option<bool>,
+    pub kir2ds3: Option<KirTyping>,
     #[serde(
         alias = "KIR2DS4",
         alias = "2DS4",
-        deserialize_with = "crate::io::ser::optional_bool_deserialize",
+        deserialize_with = "crate::io::ser::optional_kir_typing_deserialize",
         default
     )]
-    pub kir2ds4: Option<bool>,
+    pub kir2ds4: Option<KirTyping>,
     #[serde(
         alias = "KIR2DS5",
         alias = "2DS5",
-        deserialize_with = "crate::io::ser::optional_bool_deserialize",
+        deserialize_with = "crate::io::ser::optional_kir_typing_deserialize",
         default
     )]
-    pub kir2ds5: Option<bool>,
+    pub kir2ds5: Option<KirTyping>,
     #[serde(
         alias = "KIR3DS1",
         alias = "3DS1",
-        deserialize_with = "crate::io::ser::optional_bool_deserialize",
+        deserialize_with = "crate::io::ser::optional_kir_typing_deserialize",
         default
     )]
-    pub kir3ds1: Option<bool>,
+    pub kir3ds1: Option<KirTyping>,
     #[serde(
         alias = "KIR3DL1",
         alias = "3DL1",
-        deserialize_with = "crate::io::ser::optional_bool_deserialize",
+        deserialize_with = "crate::io::ser::optional_kir_typing_deserialize",
         default
     )]
-    pub kir3dl1: Option<bool>,
+    pub kir3dl1: Option<KirTyping>,
     #[serde(
         alias = "KIR3DL2",
         alias = "3DL2",
-        deserialize_with = "crate::io::ser::optional_bool_deserialize",
+        deserialize_with = "crate::io::ser::optional_kir_typing_deserialize",
         default
     )]
-    pub kir3dl2: Option<bool>,
+    pub kir3dl2: Option<KirTyping>,
+    #[serde(
+        alias = "KIR3DL3",
+        alias = "3DL3",
+        deserialize_with = "crate::io::ser::optional_kir_typing_deserialize",
+        default
+    )]
+    pub kir3dl3: Option<KirTyping>,
+    #[serde(
+        alias = "KIR2DP1",
+        alias = "2DP1",
+        deserialize_with = "crate::io::ser::optional_kir_typing_deserialize",
+        default
+    )]
+    pub kir2dp1: Option<KirTyping>,
+    #[serde(
+        alias = "KIR3DP1",
+        alias = "3DP1",
+        deserialize_with = "crate::io::ser::optional_kir_typing_deserialize",
+        default
+    )]
+    pub kir3dp1: Option<KirTyping>,
+    #[serde(
+        alias = "KIR2DS4WT",
+        alias = "2DS4WT",
+        deserialize_with = "crate::io::ser::optional_kir_typing_deserialize",
+        default
+    )]
+    pub kir2ds4wt: Option<KirTyping>,
+    #[serde(
+        alias = "KIR2DS4DEL",
+        alias = "2DS4DEL",
+        deserialize_with = "crate::io::ser::optional_kir_typing_deserialize",
+        default
+    )]
+    pub kir2ds4del: Option<KirTyping>,
+    #[serde(
+        alias = "KIR3DL1ex4",
+        alias = "3DL1ex4",
+        deserialize_with = "crate::io::ser::optional_kir_typing_deserialize",
+        default
+    )]
+    pub kir3dl1ex4: Option<KirTyping>,
+    #[serde(
+        alias = "KIR3DL1ex9",
+        alias = "3DL1ex9",
+        deserialize_with = "crate::io::ser::optional_kir_typing_deserialize",
+        default
+    )]
+    pub kir3dl1ex9: Option<KirTyping>,
+}
+
+/// Typing of a single KIR gene in the cohort input, either presence/absence, the copy number or
+/// the typed alleles
+#[derive(Debug, Clone, PartialEq)]
+pub enum KirTyping {
+    Presence(bool),
+    Copies(u8),
+    Alleles(Vec<KirAllele>),
 }
 
 macro_rules! field_to_kirs {
     ($struct_name: ident, $($fname:ident),+) => {{
-        let mut v = Vec::<Kir>::new();
-
+        let mut typed_genes = Vec::<Kir>::new();
+        let mut genes = Vec::<Kir>::new();
+        let mut alleles = Vec::<Kir>::new();
+        let mut copy_numbers = HashMap::<Kir, u8>::new();
 
         $(
-        if let Some(field)  = $struct_name.$fname {
-            if field {
-                let name = stringify!($fname).to_uppercase();
-                 v.push(name.parse::<Kir>().unwrap())
+        if let Some(field) = &$struct_name.$fname {
+            let gene = stringify!($fname).to_uppercase().parse::<Kir>().unwrap();
+            typed_genes.push(gene.clone());
+
+            if let KirTyping::Alleles(typed) = field {
+                typed.iter().for_each(|allele| alleles.push(gene.with_allele(allele.clone())));
+            }
+
+            if field.is_present() {
+                copy_numbers.insert(gene.clone(), field.copy_number());
+                genes.push(gene)
             }
         }
         )*
 
-        v
+        (typed_genes, genes, alleles, copy_numbers)
+
+    }
+    };
+}
+
+impl KirTyping {
+    /// Whether the gene or variant was detected, alleles typed imply presence
+    pub fn is_present(&self) -> bool {
+        match self {
+            KirTyping::Presence(present) => *present,
+            KirTyping::Copies(copies) => *copies > 0,
+            KirTyping::Alleles(alleles) => !alleles.is_empty(),
+        }
+    }
+
+    /// Number of gene copies, presence typing counts as a single copy and allele typing as one copy
+    /// per typed allele
+    pub fn copy_number(&self) -> u8 {
+        match self {
+            KirTyping::Presence(present) => *present as u8,
+            KirTyping::Copies(copies) => *copies,
+            KirTyping::Alleles(alleles) => alleles.len() as u8,
+        }
+    }
+}
+
+/// Typed structural variants and whether they were detected, untyped variants are left out
+fn typed_variants(cohort: &CohortTemp) -> Vec<(KirVariant, bool)> {
+    use KirVariant::*;
+
+    vec![
+        (Kir2ds4FullLength, &cohort.kir2ds4wt),
+        (Kir2ds4Deleted, &cohort.kir2ds4del),
+        (Kir3dl1Exon4, &cohort.kir3dl1ex4),
+        (Kir3dl1Exon9, &cohort.kir3dl1ex9),
+    ]
+    .into_iter()
+    .filter_map(|(variant, typing)| typing.as_ref().map(|typing| (variant, typing.is_present())))
+    .collect()
+}
+
+/// Reconciles gene presence and structural variant typing into the genes present and the
+/// effective functional genotype. Pseudogenes are never functional, KIR3DL1 is inferred from its
+/// exon typing when the gene itself was not typed (all typed exons have to be detected) and
+/// KIR2DS4 is only functional when the full-length variant is carried. When only the deleted
+/// variant was typed KIR2DS4 carriers of the deletion are considered non functional.
+fn reconcile_kir_genotype(
+    mut genes: Vec<Kir>,
+    kir3dl1_typed: bool,
+    variants: &[(KirVariant, bool)],
+) -> (Vec<Kir>, Vec<Kir>) {
+    use KirVariant::*;
+
+    let variant = |variant: KirVariant| {
+        variants
+            .iter()
+            .find(|(typed, _)| *typed == variant)
+            .map(|(_, present)| *present)
+    };
+
+    let kir3dl1 = Kir3dl1Exon4.gene();
+    if !kir3dl1_typed {
+        let exons = [variant(Kir3dl1Exon4), variant(Kir3dl1Exon9)];
+        if exons.iter().any(Option::is_some) && exons.iter().all(|exon| exon.unwrap_or(true)) {
+            genes.push(kir3dl1)
+        }
+    }
 
+    let kir2ds4 = Kir2ds4FullLength.gene();
+    let full_length = variant(Kir2ds4FullLength);
+    let deleted = variant(Kir2ds4Deleted);
+    if !genes.contains(&kir2ds4) && (full_length == Some(true) || deleted == Some(true)) {
+        genes.push(kir2ds4.clone())
     }
+
+    let kir2ds4_functional = match (full_length, deleted) {
+        (Some(full_length), _) => full_length,
+        (None, Some(true)) => false,
+        _ => true,
     };
+
+    let functional = genes
+        .iter()
+        .filter(|kir| !kir.is_pseudogene())
+        .filter(|kir| kir2ds4_functional || **kir != kir2ds4)
+        .cloned()
+        .collect();
+
+    (genes, functional)
 }
 
 impl From<CohortTemp> for Individual {
     fn from(cohort: CohortTemp) -> Self {
+        let kir_variants = typed_variants(&cohort);
+        let kir3dl1_typed = cohort.kir3dl1.is_some();
         let id = cohort.id;
 
-        let kir_genotype = field_to_kirs!(
+        let (mut kir_typed, kir_genotype, kir_alleles, kir_copy_numbers) = field_to_kirs!(
             cohort, kir2dl1, kir2dl2, kir2dl3, kir2dl4, kir2dl5, kir2ds1, kir2ds2, kir2ds3,
-            kir2ds4, kir2ds5, kir3ds1, kir3dl1, kir3dl2
+            kir2ds4, kir2ds5, kir3ds1, kir3dl1, kir3dl2, kir3dl3, kir2dp1, kir3dp1
         );
+        let (kir_genotype, kir_functional) =
+            reconcile_kir_genotype(kir_genotype, kir3dl1_typed, &kir_variants);
+        kir_variants.iter().for_each(|(variant, _)| {
+            if !kir_typed.contains(&variant.gene()) {
+                kir_typed.push(variant.gene())
+            }
+        });
 
         let hla_genotype = vec![
             cohort.a1,
@@ -188,6 +351,10 @@ impl From<CohortTemp> for Individual {
             id,
             hla_genotype,
             kir_genotype,
+            kir_functional,
+            kir_alleles,
+            kir_copy_numbers,
+            kir_typed,
         }
     }
 }
@@ -196,32 +363,178 @@ impl From<CohortTemp> for Individual {
 pub struct Individual {
     pub id: String,
     pub hla_genotype: Vec<ClassI>,
+    /// Genes detected, including pseudogenes and genes inferred from variant typing
     pub kir_genotype: Vec<Kir>,
+    /// Genes in `kir_genotype` that can encode a functional receptor
+    pub kir_functional: Vec<Kir>,
+    /// Typed alleles for the genes in `kir_genotype` where allele-level typing is available
+    pub kir_alleles: Vec<Kir>,
+    /// Copy number of the typed genes present
+    pub kir_copy_numbers: HashMap<Kir, u8>,
+    /// Genes typed in the input either directly or through variant typing, present or absent
+    pub kir_typed: Vec<Kir>,
+}
+
+/// Pair of KIR haplotypes or centromeric/telomeric motifs, `B` is the carrier of B content
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Deserialize)]
+pub enum HaplotypePair {
+    AA,
+    AB,
+    BB,
+}
+
+impl std::fmt::Display for HaplotypePair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use HaplotypePair::*;
+        let s = match self {
+            AA => "AA",
+            AB => "AB",
+            BB => "BB",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Genes only found on B haplotypes
+const B_HAPLOTYPE_KIRS: [&str; 7] = [
+    "KIR2DL2", "KIR2DL5", "KIR2DS1", "KIR2DS2", "KIR2DS3", "KIR2DS5", "KIR3DS1",
+];
+
+/// Assigns the motif pair from whether the A and B specific genes of the region are carried
+fn infer_pair(a_genes: &[Option<bool>], b_genes: &[Option<bool>]) -> Option<HaplotypePair> {
+    let any_b = b_genes.contains(&Some(true));
+    let all_a = a_genes.iter().all(|present| *present == Some(true));
+
+    if a_genes.contains(&Some(false)) {
+        Some(HaplotypePair::BB)
+    } else if !all_a {
+        None
+    } else if any_b {
+        Some(HaplotypePair::AB)
+    } else if b_genes.iter().all(Option::is_some) {
+        Some(HaplotypePair::AA)
+    } else {
+        None
+    }
 }
 
 impl Individual {
-    pub fn kir_bound_motifs<'a>(
-        &self,
-        motif_binding_map: &'a HashMap<Kir, Vec<LigandMotif>>,
-    ) -> Vec<&'a LigandMotif> {
-        self.kir_genotype
+    /// Whether the gene is carried, `None` when the gene was not typed
+    fn kir_present(&self, name: &str) -> Option<bool> {
+        let kir = name.parse::<Kir>().ok()?;
+
+        if self.kir_genotype.contains(&kir) {
+            Some(true)
+        } else if self.kir_typed.contains(&kir) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// KIR genotype from gene content, AA when none of the B haplotype specific genes are carried,
+    /// BB when a gene of the A haplotype (KIR2DL1, KIR2DL3, KIR3DL1 or KIR2DS4) is missing and AB
+    /// otherwise
+    pub fn kir_haplotypes(&self) -> Option<HaplotypePair> {
+        let a_genes = ["KIR2DL1", "KIR2DL3", "KIR3DL1", "KIR2DS4"]
+            .iter()
+            .map(|name| self.kir_present(name))
+            .collect::<Vec<Option<bool>>>();
+        let b_genes = B_HAPLOTYPE_KIRS
+            .iter()
+            .map(|name| self.kir_present(name))
+            .collect::<Vec<Option<bool>>>();
+
+        infer_pair(&a_genes, &b_genes)
+    }
+
+    /// Centromeric motifs, Cen-A carries KIR2DL3 and Cen-B carries KIR2DL2 and/or KIR2DS2
+    pub fn cen_motifs(&self) -> Option<HaplotypePair> {
+        let a_genes = [self.kir_present("KIR2DL3")];
+        let b_genes = [self.kir_present("KIR2DL2"), self.kir_present("KIR2DS2")];
+
+        infer_pair(&a_genes, &b_genes)
+    }
+
+    /// Telomeric motifs, Tel-A carries KIR3DL1 and KIR2DS4 and Tel-B carries KIR3DS1 and/or KIR2DS1
+    pub fn tel_motifs(&self) -> Option<HaplotypePair> {
+        let a_genes = [self.kir_present("KIR3DL1"), self.kir_present("KIR2DS4")];
+        let b_genes = [self.kir_present("KIR3DS1"), self.kir_present("KIR2DS1")];
+
+        infer_pair(&a_genes, &b_genes)
+    }
+
+    /// Copy number of the gene, genes only inferred from variant typing count as a single copy
+    pub fn kir_copy_number(&self, kir: &Kir) -> u8 {
+        match self.kir_copy_numbers.get(&kir.gene()) {
+            Some(copies) => *copies,
+            _ if self.kir_genotype.contains(&kir.gene()) => 1,
+            _ => 0,
+        }
+    }
+
+    /// Functional genes expressed on the cell surface. A gene typed at allele level is
+    /// only expressed when at least one of its alleles is not a null allele or null allotype,
+    /// alleles missing from the allotype table are assumed to be expressed
+    pub fn expressed_kirs(&self, allotypes: &HashMap<Kir, KirExpression>) -> Vec<Kir> {
+        self.kir_functional
             .iter()
-            .fold(Vec::<&LigandMotif>::new(), |mut bound_motifs, kir| {
-                if let Some(motifs) = motif_binding_map.get(&kir) {
-                    motifs.iter().for_each(|motif| bound_motifs.push(motif));
-                }
-                bound_motifs
+            .filter(|gene| {
+                let mut alleles = self
+                    .kir_alleles
+                    .iter()
+                    .filter(|kir| &kir.gene() == *gene)
+                    .peekable();
+
+                alleles.peek().is_none()
+                    || alleles.any(|kir| {
+                        let null_allele = matches!(kir.allele(), Some(allele) if allele.is_null());
+                        let expression = allotypes
+                            .get(&kir.allotype())
+                            .copied()
+                            .unwrap_or(KirExpression::Expressed);
+
+                        !null_allele && expression.is_expressed()
+                    })
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Expressed inhibitory KIRs educated by a cognate ligand motif in the HLA genotype, paired
+    /// with the strongest interaction with the individual's motifs
+    pub fn educated_kirs(
+        &self,
+        kir_binding: &KirBindingTable,
+        allotypes: &HashMap<Kir, KirExpression>,
+        hla_ligand_map: &KirLigandMap,
+        ligand_policy: AmbiguityPolicy,
+    ) -> Vec<(Kir, f32)> {
+        let hla_motifs = self.get_hla_motifs(hla_ligand_map, ligand_policy);
+
+        self.expressed_kirs(allotypes)
+            .into_iter()
+            .filter(Kir::is_inhibitory)
+            .filter_map(|kir| {
+                hla_motifs
+                    .iter()
+                    .map(|motif| kir_binding.strength(&kir, motif))
+                    .filter(|strength| *strength > 0.0)
+                    .max_by(|a, b| a.partial_cmp(b).expect("Tried to compare a NaN"))
+                    .map(|strength| (kir, strength))
             })
+            .collect()
     }
 
-    pub fn get_hla_motifs<'a>(&self, hla_ligand_map: &'a KirLigandMap) -> Vec<&'a LigandMotif> {
+    pub fn get_hla_motifs<'a>(
+        &self,
+        hla_ligand_map: &'a KirLigandMap,
+        ligand_policy: AmbiguityPolicy,
+    ) -> Vec<&'a LigandMotif> {
         self.hla_genotype
             .iter()
             .fold(Vec::<&LigandMotif>::new(), |mut hla_motifs, hla| {
-                let mut info = hla_ligand_map.get_allele_info(&hla);
-                info.sort();
-
-                if let Some(info) = info.iter().next() {
+                if let Some(info) = hla_ligand_map.lookup(hla).resolve(ligand_policy).info() {
                     hla_motifs.push(info.motif())
                 };
 
@@ -233,29 +546,153 @@ impl Individual {
 #[cfg(test)]
 mod tests {
     use crate::cohort::Individual;
-    use crate::io::reader::{read_kir_motif_binding, read_temp_cohort};
+    use crate::io::reader::{read_kir_allotypes, read_kir_motif_binding, read_temp_cohort};
+    use crate::test_utils::cohort;
+    use immunoprot::ig_like::kir::Kir;
 
     #[test]
     fn test_read_cohort() {
         let cohort = read_temp_cohort("tests/input/cohorts/example_cohort.csv").unwrap();
+
+        assert_eq!(cohort.len(), 14);
     }
 
     #[test]
     fn test_create_individual() {
-        let cohort = read_temp_cohort("tests/input/cohorts/example_cohort.csv").unwrap();
-        let individuals = cohort
+        let individuals = read_temp_cohort("tests/input/cohorts/example_cohort.csv")
+            .unwrap()
             .into_iter()
-            .map(|temp| Individual::from(temp))
+            .map(Individual::from)
             .collect::<Vec<Individual>>();
+
+        assert_eq!(individuals[0].id, "1");
+        assert!(individuals
+            .iter()
+            .all(|individual| individual.hla_genotype.len() == 6));
     }
 
     #[test]
-    fn test_get_bound_motifs() {
-        let cohort = read_temp_cohort("tests/input/cohorts/example_cohort.csv").unwrap();
-        let individuals = cohort
+    fn test_expressed_kir_alleles() {
+        let input = "ID,A1,A2,B1,B2,C1,C2,KIR3DL1,KIR2DL1\n\
+                     1,A*01:01,A*02:01,B*57:01,B*08:01,C*07:01,C*06:02,004+00501,1\n\
+                     2,A*01:01,A*02:01,B*57:01,B*08:01,C*07:01,C*06:02,KIR3DL1*004/KIR3DL1*019,1\n";
+        let individuals = cohort(input);
+        let allotypes = read_kir_allotypes();
+        let kir3dl1 = "KIR3DL1".parse::<Kir>().unwrap();
+
+        assert_eq!(individuals[0].kir_alleles.len(), 2);
+        assert!(individuals[0].expressed_kirs(&allotypes).contains(&kir3dl1));
+        assert!(!individuals[1].expressed_kirs(&allotypes).contains(&kir3dl1));
+        assert_eq!(individuals[1].expressed_kirs(&allotypes).len(), 1);
+    }
+
+    #[test]
+    fn test_functional_kir_genotype() {
+        let individuals = read_temp_cohort("tests/input/cohorts/example_cohort.csv")
+            .unwrap()
             .into_iter()
-            .map(|temp| Individual::from(temp))
+            .map(Individual::from)
             .collect::<Vec<Individual>>();
-        let motif_binding_map = read_kir_motif_binding();
+        let kir2ds4 = "KIR2DS4".parse::<Kir>().unwrap();
+        let kir3dl1 = "KIR3DL1".parse::<Kir>().unwrap();
+        let kir3dp1 = "KIR3DP1".parse::<Kir>().unwrap();
+
+        // Individual 2 only carries the deleted KIR2DS4 variant
+        assert!(individuals[1].kir_genotype.contains(&kir2ds4));
+        assert!(!individuals[1].kir_functional.contains(&kir2ds4));
+        assert!(individuals[0].kir_functional.contains(&kir2ds4));
+
+        assert!(individuals[0].kir_genotype.contains(&kir3dl1));
+        assert!(individuals[0].kir_genotype.contains(&kir3dp1));
+        assert!(!individuals[0].kir_functional.contains(&kir3dp1));
+    }
+
+    #[test]
+    fn test_kir_copy_number() {
+        let input = "ID,A1,A2,B1,B2,C1,C2,KIR2DL1,KIR3DL1,KIR2DL3\n\
+                     1,A*01:01,A*02:01,B*57:01,B*08:01,C*07:01,C*06:02,2,001+004,1\n\
+                     2,A*01:01,A*02:01,B*57:01,B*08:01,C*07:01,C*06:02,0,3,NA\n";
+        let individuals = cohort(input);
+        let kir2dl1 = "KIR2DL1".parse::<Kir>().unwrap();
+        let kir3dl1 = "KIR3DL1".parse::<Kir>().unwrap();
+        let kir2dl3 = "KIR2DL3".parse::<Kir>().unwrap();
+
+        assert_eq!(individuals[0].kir_copy_number(&kir2dl1), 2);
+        assert_eq!(individuals[0].kir_copy_number(&kir3dl1), 2);
+        assert_eq!(individuals[0].kir_copy_number(&kir2dl3), 1);
+        assert_eq!(individuals[1].kir_copy_number(&kir2dl1), 0);
+        assert_eq!(individuals[1].kir_copy_number(&kir3dl1), 3);
+        assert!(!individuals[1].kir_genotype.contains(&kir2dl1));
+    }
+
+    #[test]
+    fn test_kir_haplotypes() {
+        use crate::cohort::HaplotypePair::*;
+
+        let input = "ID,A1,A2,B1,B2,C1,C2,KIR2DL1,KIR2DL2,KIR2DL3,KIR2DL5,KIR2DS1,KIR2DS2,KIR2DS3,KIR2DS4,KIR2DS5,KIR3DL1,KIR3DS1\n\
+                     1,A*01:01,A*02:01,B*57:01,B*08:01,C*07:01,C*06:02,1,0,1,0,0,0,0,1,0,1,0\n\
+                     2,A*01:01,A*02:01,B*57:01,B*08:01,C*07:01,C*06:02,1,1,1,0,1,1,0,1,0,1,1\n\
+                     3,A*01:01,A*02:01,B*57:01,B*08:01,C*07:01,C*06:02,1,1,0,1,1,1,0,1,0,0,1\n\
+                     4,A*01:01,A*02:01,B*57:01,B*08:01,C*07:01,C*06:02,1,0,1,NA,0,0,0,1,0,1,0\n";
+        let individuals = cohort(input);
+
+        let inferred = individuals
+            .iter()
+            .map(|individual| {
+                (
+                    individual.kir_haplotypes(),
+                    individual.cen_motifs(),
+                    individual.tel_motifs(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(inferred[0], (Some(AA), Some(AA), Some(AA)));
+        assert_eq!(inferred[1], (Some(AB), Some(AB), Some(AB)));
+        assert_eq!(inferred[2], (Some(BB), Some(BB), Some(BB)));
+        assert_eq!(inferred[3], (None, Some(AA), Some(AA)));
+    }
+
+    #[test]
+    fn test_educated_kirs() {
+        use crate::calc::KirBindingTable;
+        use immunoprot::ig_like::kir_ligand::{AmbiguityPolicy, KirLigandMap, LigandMotif};
+
+        let input = "ID,A1,A2,B1,B2,C1,C2,KIR2DL1,KIR2DL3,KIR2DS1,KIR3DL1\n\
+                     1,A*01:01,A*02:01,B*57:01,B*08:01,C*07:01,C*06:02,1,1,1,004\n";
+        let individual = cohort(input).remove(0);
+        let educated = individual.educated_kirs(
+            &read_kir_motif_binding(),
+            &read_kir_allotypes(),
+            &KirLigandMap::init().unwrap(),
+            AmbiguityPolicy::First,
+        );
+        let educated = educated
+            .into_iter()
+            .map(|(kir, _)| kir.to_string())
+            .collect::<Vec<String>>();
+
+        // KIR3DL1*004 is not expressed despite Bw4 being present and KIR2DS1 is activating
+        assert_eq!(educated, vec!["KIR2DL1", "KIR2DL3"]);
+
+        // Each educated iKIR keeps its strongest interaction with the C1 and C2 motifs
+        let mut binding = KirBindingTable::default();
+        binding.insert("KIR2DL1".parse().unwrap(), LigandMotif::C2, 0.5);
+        binding.insert("KIR2DL3".parse().unwrap(), LigandMotif::C1, 0.8);
+        binding.insert("KIR2DL3".parse().unwrap(), LigandMotif::C2, 0.2);
+        let strengths = individual
+            .educated_kirs(
+                &binding,
+                &read_kir_allotypes(),
+                &KirLigandMap::init().unwrap(),
+                AmbiguityPolicy::First,
+            )
+            .into_iter()
+            .map(|(kir, strength)| (kir.to_string(), strength))
+            .collect::<Vec<(String, f32)>>();
+        assert_eq!(
+            strengths,
+            vec![("KIR2DL1".to_string(), 0.5), ("KIR2DL3".to_string(), 0.8)]
+        );
     }
 }
@@ -6,7 +6,7 @@ pub enum Error {
     #[error("Could not parse motif positions")]
     IncorrectMotifPositions(#[from] std::num::ParseIntError),
     #[error("Could not load any kir ligand information")]
-    KirLigandMapError,
+    CouldNotLoadKirLigandMap,
     #[error("Could not create output directory")]
     CouldNotCreateOutputDir,
     #[error("Could not create output file")]
@@ -15,14 +15,88 @@ pub enum Error {
     CouldNotWriteAlleleMeta,
     #[error("Could not write binding metadata to output.")]
     CouldNotWriteBindingMeta,
+    #[error("Could not write ambiguous kir ligand motifs to output.")]
+    CouldNotWriteAmbiguousLigands,
     #[error("Could not write fraction shared results to output.")]
     CouldNotWriteFsResult,
     #[error("Could not write cohort calculation results to output")]
     CouldNotWriteCohortResult,
+    #[error("Could not write NK education results to output")]
+    CouldNotWriteNkEducation,
     #[error("Could not open cohort file:\n{:?}", .0.kind().display())]
     CouldNotOpenCohortFile(#[from] csv::Error),
     #[error("No global config directory exists")]
     NoGlobalConfigDir,
+    #[error("Could not read kir ligand information from '{}':\n{1}", .0.display())]
+    CouldNotReadLigandFile(std::path::PathBuf, immunoprot::error::IoError),
+    #[error("Could not write kir ligand table to '{}'", .0.display())]
+    CouldNotWriteLigandTable(std::path::PathBuf),
+    #[error("Could not write kir ligand table differences")]
+    CouldNotWriteLigandDiff,
+    #[error("Kir ligand table version '{0}' does not exist, available versions are listed by `ligand list`")]
+    UnknownLigandVersion(String),
+    #[error("'{0}' can not be used as a kir ligand table version name")]
+    InvalidLigandVersionName(String),
+    #[error("Could not set the active kir ligand table version to '{0}'")]
+    CouldNotSetActiveLigandVersion(String),
+    #[error("Could not read kir motif binding table '{}'", .0.display())]
+    CouldNotReadKirBinding(std::path::PathBuf),
+    #[error(
+        "Invalid receptor ligand '{0}', expected an allele, an allele group or a kir ligand motif"
+    )]
+    InvalidReceptorLigand(String),
+    #[error(
+        "Invalid receptor table entry '{0}', entries need a receptor, a ligand and a finite weight"
+    )]
+    InvalidReceptorEntry(String),
+    #[error("Could not read receptor table '{}'", .0.display())]
+    CouldNotReadReceptorTable(std::path::PathBuf),
+    #[error("Unknown LILRB option '{0}'")]
+    UnknownLilrbOption(String),
+    #[error("Could not read LILRB table '{}'", .0.display())]
+    CouldNotReadLilrbTable(std::path::PathBuf),
+    #[error("Invalid LILRB table entry '{0}', entries need two alleles and the LILRB1 and LILRB2 scores")]
+    InvalidLilrbEntry(String),
+    #[error("Could not write LILRB results")]
+    CouldNotWriteLilrbResult,
+    #[error("Could not write receptor results")]
+    CouldNotWriteReceptorResult,
+    #[error("Invalid binding threshold '{0}', expected `weak`, `strong`, a %rank between 0 and 100 or an affinity such as `500nM`")]
+    InvalidBindingThreshold(String),
+    #[error(
+        "Invalid allele threshold entry '{0}', entries need an allele and a binding threshold"
+    )]
+    InvalidAlleleThreshold(String),
+    #[error("Could not read allele thresholds '{}'", .0.display())]
+    CouldNotReadAlleleThresholds(std::path::PathBuf),
+    #[error("Invalid bootstrap with {0} replicates and a confidence level of {1}, bootstraps need at least 2 replicates and a level between 0 and 1")]
+    InvalidBootstrap(usize, f32),
+    #[error("Invalid null model with {0} replicates, the null model needs at least 1 replicate")]
+    InvalidNullReplicates(usize),
+    #[error("Unknown binding weight '{0}'")]
+    UnknownBindingWeight(String),
+    #[error("The affinity binding weight requires binding predictions with binding affinity")]
+    MissingAffinity,
+    #[error("The affinity binding weight does not depend on the binding threshold and cannot be used with --threshold-sweep")]
+    AffinityThresholdSweep,
+    #[error("Unknown overlap metric '{0}'")]
+    UnknownOverlapMetric(String),
+    #[error("Invalid shard '{0}', expected `i/n` with i between 1 and n")]
+    InvalidShard(String),
+    #[error("Could not find the shard information in '{}'", .0.display())]
+    MissingShardInfo(std::path::PathBuf),
+    #[error("Shards can not be merged, {0}")]
+    InconsistentShards(String),
+    #[error("Could not merge '{0}' of the shards")]
+    CouldNotMergeShards(String),
+    #[error("Could not write shard information")]
+    CouldNotWriteShardInfo,
+    #[error("Invalid kir peptide rule '{0}', rules need a receptor, a peptide position (1-9), residues and `enable` or `abolish`")]
+    InvalidKirPeptideRule(String),
+    #[error("Could not read kir peptide rules '{}'", .0.display())]
+    CouldNotReadKirPeptideRules(std::path::PathBuf),
+    #[error("Invalid kir motif binding strength in line '{0}', strengths have to be non-negative numbers")]
+    InvalidKirBindingStrength(String),
 }
 
 trait ErrorKindDisplay {
@@ -1,26 +1,15 @@
-use crate::calc::LilrbScore;
+use crate::calc::{
+    BindingThreshold, KirBindingTable, KirLigandKey, KirLigandScores, KirPeptideRules, LilrbScore,
+    LilrbScores, PeptideRule,
+};
 use crate::cohort::CohortTemp;
 use crate::error::Error;
-use immunoprot::ig_like::kir::Kir;
-use immunoprot::ig_like::kir_ligand::{KirLigandMap, LigandMotif};
+use crate::receptor::{ReceptorLigand, ReceptorTable};
+use immunoprot::ig_like::kir::{Kir, KirExpression};
+use immunoprot::ig_like::kir_ligand::LigandMotif;
 use immunoprot::mhc::hla::ClassI;
-use log::info;
 use std::collections::HashMap;
 
-pub fn read_project_ligand_info() -> Option<KirLigandMap> {
-    let mut kir_ligand_map = None;
-    if let Some(project_dir) = directories::ProjectDirs::from("", "", "fstool") {
-        let path = project_dir.data_dir();
-        kir_ligand_map = KirLigandMap::from_path(path.join(crate::PROJECT_LIGAND_TABLE)).ok()
-    }
-
-    if kir_ligand_map.is_none() {
-        info!("No local kir ligand info found. Defaulting to: https://raw.githubusercontent.com/bjohnnyd/fs-tool/dev_fs/immunoprot/src/resources/allele_motifs.tsv");
-        kir_ligand_map = KirLigandMap::init().ok();
-    }
-    kir_ligand_map
-}
-
 pub fn read_temp_cohort<P>(path: P) -> Result<Vec<CohortTemp>, Error>
 where
     P: AsRef<std::path::Path>,
@@ -40,67 +29,403 @@ where
     Ok(cohort_temp)
 }
 
-pub fn read_kir_motif_binding() -> HashMap<Kir, Vec<LigandMotif>> {
-    crate::KIR_MOTIF_BINDING
+pub fn read_kir_motif_binding() -> KirBindingTable {
+    parse_kir_binding(crate::KIR_MOTIF_BINDING).expect("Bundled kir motif binding table is invalid")
+}
+
+/// Reads a user supplied kir motif binding table in the format of the bundled table
+pub fn read_kir_binding_table<P>(path: P) -> Result<KirBindingTable, Error>
+where
+    P: AsRef<std::path::Path>,
+{
+    let table = std::fs::read_to_string(&path)
+        .map_err(|_| Error::CouldNotReadKirBinding(path.as_ref().to_path_buf()))?;
+
+    parse_kir_binding(&table)
+}
+
+/// Parses tab separated lines of a KIR, its ';' separated ligand motifs, an optional strength
+/// applied to all motifs on the line (1.0 when missing) and an optional source of the strength.
+/// Lines with unknown KIRs or motifs are ignored and a KIR can be listed on several lines to assign
+/// different strengths.
+fn parse_kir_binding(table: &str) -> Result<KirBindingTable, Error> {
+    table
         .lines()
-        .fold(HashMap::new(), |mut motif_binding, line| {
+        .filter(|line| !line.starts_with('#') && !line.trim().is_empty())
+        .try_fold(KirBindingTable::default(), |mut binding, line| {
             let kir_motif = line.split('\t').collect::<Vec<&str>>();
 
-            let motifs = kir_motif[1]
+            let strength = match kir_motif.get(2).map(|strength| strength.trim()) {
+                Some(strength) if !strength.is_empty() => strength
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|strength| *strength >= 0.0)
+                    .ok_or_else(|| Error::InvalidKirBindingStrength(line.to_string()))?,
+                _ => 1.0,
+            };
+
+            let motifs = kir_motif
+                .get(1)
+                .unwrap_or(&"")
                 .split(';')
                 .filter_map(|motif| motif.parse::<LigandMotif>().ok())
                 .collect::<Vec<LigandMotif>>();
 
-            if !motifs.is_empty() {
-                if let Ok(kir) = kir_motif[0].parse::<Kir>() {
-                    motif_binding.insert(kir, motifs);
+            if let Ok(kir) = kir_motif[0].parse::<Kir>() {
+                motifs
+                    .into_iter()
+                    .for_each(|motif| binding.insert(kir.clone(), motif, strength));
+            }
+
+            Ok(binding)
+        })
+}
+
+/// Reads the bundled KIR allotype expression phenotypes, keyed by the allotype (e.g. KIR3DL1*004)
+pub fn read_kir_allotypes() -> HashMap<Kir, KirExpression> {
+    crate::KIR_ALLOTYPES
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .fold(HashMap::new(), |mut allotypes, line| {
+            let entry = line.split('\t').collect::<Vec<&str>>();
+
+            if entry.len() == 2 {
+                if let (Ok(kir), Ok(expression)) =
+                    (entry[0].parse::<Kir>(), entry[1].parse::<KirExpression>())
+                {
+                    allotypes.insert(kir.allotype(), expression);
                 }
             }
 
-            motif_binding
+            allotypes
         })
 }
 
-// TODO: might be better with a proper reader and serializing
-pub fn read_lilrb_scores() -> Vec<LilrbScore> {
-    crate::LILRB_SIMSCORES
+pub fn read_kir_peptide_rules() -> KirPeptideRules {
+    parse_kir_peptide_rules(crate::KIR_PEPTIDE_RULES)
+        .expect("Bundled kir peptide rules are invalid")
+}
+
+/// Reads user supplied kir peptide rules in the format of the bundled rules
+pub fn read_kir_peptide_rules_table<P>(path: P) -> Result<KirPeptideRules, Error>
+where
+    P: AsRef<std::path::Path>,
+{
+    let table = std::fs::read_to_string(&path)
+        .map_err(|_| Error::CouldNotReadKirPeptideRules(path.as_ref().to_path_buf()))?;
+
+    parse_kir_peptide_rules(&table)
+}
+
+/// Parses tab separated lines of a KIR, the 9mer peptide position, the residues and whether the
+/// residues `enable` or `abolish` binding
+fn parse_kir_peptide_rules(table: &str) -> Result<KirPeptideRules, Error> {
+    let rules = table
         .lines()
-        .filter(|line| !line.contains("lilrb") && !line.starts_with('#'))
-        .fold(Vec::new(), |mut scores, line| {
+        .filter(|line| !line.starts_with('#') && !line.trim().is_empty())
+        .map(|line| {
             let entry = line.split('\t').collect::<Vec<&str>>();
+            let invalid = || Error::InvalidKirPeptideRule(line.to_string());
+
+            if entry.len() != 4 {
+                return Err(invalid());
+            }
+
+            let kir = entry[0].trim().parse::<Kir>().map_err(|_| invalid())?;
+            let position = entry[1]
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .filter(|position| (1..=9).contains(position))
+                .ok_or_else(invalid)?;
+            let effect = entry[3].parse().map_err(|_| invalid())?;
+
+            Ok(PeptideRule {
+                kir,
+                position,
+                residues: entry[2].trim().to_uppercase(),
+                effect,
+            })
+        })
+        .collect::<Result<Vec<PeptideRule>, Error>>()?;
 
-            let first_allele = entry[0].parse::<ClassI>();
-            let second_allele = entry[1].parse::<ClassI>();
-            let lilrb1_score = entry[2].parse::<f32>();
-            let lilrb2_score = entry[3].parse::<f32>();
+    Ok(KirPeptideRules { rules })
+}
 
-            if let (Ok(first_allele), Ok(second_allele), Ok(lilrb1_score), Ok(lilrb2_score)) =
-                (first_allele, second_allele, lilrb1_score, lilrb2_score)
+/// Reads a user supplied receptor table, a receptor can be listed on several lines
+pub fn read_receptor_tables<P>(path: P) -> Result<Vec<ReceptorTable>, Error>
+where
+    P: AsRef<std::path::Path>,
+{
+    let table = std::fs::read_to_string(&path)
+        .map_err(|_| Error::CouldNotReadReceptorTable(path.as_ref().to_path_buf()))?;
+
+    parse_receptor_tables(&table)
+}
+
+/// Parses tab separated lines of a receptor, the ligand (allele, allele group or ligand motif) and
+/// the weight of the engagement, receptors are in the order they first appear
+pub(crate) fn parse_receptor_tables(table: &str) -> Result<Vec<ReceptorTable>, Error> {
+    table
+        .lines()
+        .filter(|line| !line.starts_with('#') && !line.trim().is_empty())
+        .try_fold(Vec::<ReceptorTable>::new(), |mut receptors, line| {
+            let entry = line.split('\t').collect::<Vec<&str>>();
+            let invalid = || Error::InvalidReceptorEntry(line.to_string());
+
+            if entry.len() != 3 {
+                return Err(invalid());
+            }
+
+            let receptor = entry[0].trim();
+            let ligand = entry[1].parse::<ReceptorLigand>()?;
+            // NaN or infinite weights can not be compared when the best matching allele is chosen
+            let weight = entry[2]
+                .trim()
+                .parse::<f32>()
+                .ok()
+                .filter(|weight| weight.is_finite())
+                .ok_or_else(invalid)?;
+
+            match receptors
+                .iter_mut()
+                .find(|table| table.receptor == receptor)
             {
-                scores.push(LilrbScore {
-                    first_allele,
-                    second_allele,
-                    lilrb1_score,
-                    lilrb2_score,
-                });
+                Some(table) => {
+                    table.weights.insert(ligand, weight);
+                }
+                _ => {
+                    let mut table = ReceptorTable::new(receptor);
+                    table.weights.insert(ligand, weight);
+                    receptors.push(table);
+                }
+            }
+
+            Ok(receptors)
+        })
+}
+
+pub fn read_allele_thresholds<P>(path: P) -> Result<HashMap<ClassI, BindingThreshold>, Error>
+where
+    P: AsRef<std::path::Path>,
+{
+    let table = std::fs::read_to_string(&path)
+        .map_err(|_| Error::CouldNotReadAlleleThresholds(path.as_ref().to_path_buf()))?;
+
+    parse_allele_thresholds(&table)
+}
+
+/// Parses tab separated lines of an allele and its binding threshold (`weak`, `strong`, a %rank or
+/// an affinity such as `500nM`)
+pub(crate) fn parse_allele_thresholds(
+    table: &str,
+) -> Result<HashMap<ClassI, BindingThreshold>, Error> {
+    table
+        .lines()
+        .filter(|line| !line.starts_with('#') && !line.trim().is_empty())
+        .map(|line| {
+            let invalid = || Error::InvalidAlleleThreshold(line.to_string());
+
+            match line.split('\t').collect::<Vec<&str>>().as_slice() {
+                [allele, threshold] => Ok((
+                    allele.trim().parse::<ClassI>().map_err(|_| invalid())?,
+                    threshold.parse::<BindingThreshold>()?,
+                )),
+                _ => Err(invalid()),
+            }
+        })
+        .collect()
+}
+
+/// Reads the bundled KIR ligand scores, rows with unknown ligand classes or alleles are ignored
+pub fn read_kir_ligand_scores() -> KirLigandScores {
+    crate::KIR_SIMSCORES
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .fold(KirLigandScores::default(), |mut kir_scores, line| {
+            let entry = line.split('\t').collect::<Vec<&str>>();
+
+            if entry.len() == 3 {
+                if let (Ok(first), Ok(second), Ok(score)) = (
+                    entry[0].parse::<KirLigandKey>(),
+                    entry[1].parse::<KirLigandKey>(),
+                    entry[2].trim().parse::<f32>(),
+                ) {
+                    kir_scores.scores.insert((first, second), score);
+                }
             }
 
-            scores
+            kir_scores
+        })
+}
+
+/// Reads the bundled LILRB similarity table, rows with alleles that can not be parsed are ignored
+pub fn read_lilrb_scores() -> LilrbScores {
+    LilrbScores::new(
+        lilrb_entries(crate::LILRB_SIMSCORES)
+            .filter_map(Result::ok)
+            .collect(),
+    )
+}
+
+/// Reads a user supplied LILRB similarity table in the format of the bundled table
+pub fn read_lilrb_table<P>(path: P) -> Result<LilrbScores, Error>
+where
+    P: AsRef<std::path::Path>,
+{
+    let table = std::fs::read_to_string(&path)
+        .map_err(|_| Error::CouldNotReadLilrbTable(path.as_ref().to_path_buf()))?;
+
+    parse_lilrb_table(&table)
+}
+
+/// Parses a LILRB similarity table, unlike the bundled table every row has to be valid
+pub(crate) fn parse_lilrb_table(table: &str) -> Result<LilrbScores, Error> {
+    Ok(LilrbScores::new(
+        lilrb_entries(table).collect::<Result<Vec<LilrbScore>, Error>>()?,
+    ))
+}
+
+// TODO: might be better with a proper reader and serializing
+fn lilrb_entries(table: &str) -> impl Iterator<Item = Result<LilrbScore, Error>> + '_ {
+    table
+        .lines()
+        .filter(|line| !line.contains("lilrb") && !line.starts_with('#') && !line.trim().is_empty())
+        .map(|line| {
+            let invalid = || Error::InvalidLilrbEntry(line.to_string());
+
+            match line.split('\t').collect::<Vec<&str>>().as_slice() {
+                [first_allele, second_allele, lilrb1_score, lilrb2_score, ..] => Ok(LilrbScore {
+                    first_allele: first_allele.parse::<ClassI>().map_err(|_| invalid())?,
+                    second_allele: second_allele.parse::<ClassI>().map_err(|_| invalid())?,
+                    lilrb1_score: lilrb1_score.trim().parse::<f32>().map_err(|_| invalid())?,
+                    lilrb2_score: lilrb2_score.trim().parse::<f32>().map_err(|_| invalid())?,
+                }),
+                _ => Err(invalid()),
+            }
         })
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::io::reader::{read_kir_motif_binding, read_lilrb_scores};
+    use crate::calc::{BindingCutoff, BindingThreshold};
+    use crate::io::reader::{
+        parse_allele_thresholds, parse_kir_binding, parse_lilrb_table, read_kir_allotypes,
+        read_kir_ligand_scores, read_kir_motif_binding, read_lilrb_scores,
+    };
+    use immunoprot::ig_like::kir::{Kir, KirExpression};
+    use immunoprot::ig_like::kir_ligand::LigandMotif;
+    use immunoprot::mhc::hla::ClassI;
 
     #[test]
     fn test_create_motif_binding() {
         let motif_binding = read_kir_motif_binding();
-        assert_eq!(motif_binding.len(), 9);
+        assert_eq!(motif_binding.motifs.len(), 9);
+    }
+
+    #[test]
+    fn test_kir_binding_strengths() {
+        let binding = read_kir_motif_binding();
+        let kir2dl1 = "KIR2DL1".parse::<Kir>().unwrap();
+        let kir2dl2 = "KIR2DL2".parse::<Kir>().unwrap();
+
+        // The bundled interactions all have the default strength
+        assert_eq!(binding.strength(&kir2dl1, &LigandMotif::C2), 1.0);
+        assert_eq!(binding.strength(&kir2dl2, &LigandMotif::C2), 1.0);
+        assert_eq!(binding.strength(&kir2dl1, &LigandMotif::C1), 0.0);
+        assert!(binding.strengths.values().all(|strength| *strength == 1.0));
+
+        let table = "KIR2DL3\tC1\nKIR2DL3\tC2\t0.2\tdoi:10.0000/example\n";
+        let kir2dl3 = "KIR2DL3".parse::<Kir>().unwrap();
+        let binding = parse_kir_binding(table).unwrap();
+        assert_eq!(binding.motifs[&kir2dl3].len(), 2);
+        assert_eq!(binding.strength(&kir2dl3, &LigandMotif::C2), 0.2);
+        assert!(parse_kir_binding("KIR2DL3\tC1\tstrong").is_err());
     }
+    #[test]
+    fn test_read_kir_allotypes() {
+        let allotypes = read_kir_allotypes();
+        let null = "KIR3DL1*004".parse::<Kir>().unwrap();
+
+        assert_eq!(allotypes.get(&null), Some(&KirExpression::Null));
+        assert!(allotypes.keys().all(|kir| kir.allele().is_some()));
+    }
+
+    #[test]
+    fn test_read_kir_ligand_scores() {
+        let kir_scores = read_kir_ligand_scores();
+        let c1 = "C*01:02".parse::<ClassI>().unwrap();
+        let c2 = "C*02:02".parse::<ClassI>().unwrap();
+        let b46 = "B*46:01:01".parse::<ClassI>().unwrap();
+        let b57 = "B*57:01".parse::<ClassI>().unwrap();
+        let b08 = "B*08:01".parse::<ClassI>().unwrap();
+
+        assert_eq!(kir_scores.scores.len(), 16);
+        assert_eq!(
+            kir_scores.score((&c1, Some(&LigandMotif::C1)), (&c2, Some(&LigandMotif::C2))),
+            Some(1.0)
+        );
+        assert_eq!(
+            kir_scores.score(
+                (&b57, Some(&LigandMotif::Bw4_80I)),
+                (&b08, Some(&LigandMotif::Bw6))
+            ),
+            Some(5.0)
+        );
+        // B*46:01 is scored as a C1 ligand regardless of its motif
+        assert_eq!(
+            kir_scores.score(
+                (&c1, Some(&LigandMotif::C1)),
+                (&b46, Some(&LigandMotif::Bw6))
+            ),
+            Some(0.0)
+        );
+        assert_eq!(
+            kir_scores.score(
+                (&c1, Some(&LigandMotif::C1)),
+                (&b57, Some(&LigandMotif::Bw4_80I))
+            ),
+            None
+        );
+    }
+
     #[test]
     fn test_create_lilrb_scores() {
         let lilrb_scores = read_lilrb_scores();
-        dbg!(lilrb_scores);
+        assert_eq!(lilrb_scores.scores.len(), 4560);
+    }
+
+    #[test]
+    fn test_parse_lilrb_table() {
+        let header = "first_allele\tsecond_allele\tlilrb1_simscore\tlilrb2_simscore\n";
+        let lilrb_scores =
+            parse_lilrb_table(&format!("{}HLA-A01:01\tHLA-A02:01\t0.58\t0.94\n", header)).unwrap();
+        assert_eq!(lilrb_scores.scores.len(), 1);
+
+        assert!(parse_lilrb_table(&format!("{}HLA-A01:01\tHLA-A02:01\t0.58\n", header)).is_err());
+        assert!(
+            parse_lilrb_table(&format!("{}HLA-A01:01\tHLA-A02:01\thigh\t0.94\n", header)).is_err()
+        );
+        assert!(parse_lilrb_table(&format!(
+            "{}HLA-A01:01\tnot an allele\t0.58\t0.94\n",
+            header
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_allele_thresholds() {
+        let thresholds =
+            parse_allele_thresholds("# allele\tthreshold\nA*01:01\tstrong\nB*27:05\t500nM\n")
+                .unwrap();
+        let b2705 = "B*27:05".parse::<ClassI>().unwrap();
+
+        assert_eq!(thresholds.len(), 2);
+        assert_eq!(
+            thresholds[&b2705],
+            BindingThreshold::Cutoff(BindingCutoff::Affinity(500.0))
+        );
+        assert!(parse_allele_thresholds("A*01:01\t2\t3").is_err());
+        assert!(parse_allele_thresholds("A*01:01\tmedium").is_err());
     }
 }
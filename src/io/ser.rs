@@ -1,7 +1,9 @@
 use serde::de::{self, Visitor};
 use serde::{ser::SerializeStruct, Deserialize, Deserializer, Serialize, Serializer};
 
+use crate::cohort::KirTyping;
 use crate::meta::{AlleleMeta, LigandMeta};
+use immunoprot::ig_like::kir::KirAllele;
 use immunoprot::ig_like::kir_ligand::{KirLigandInfo, LigandMotif};
 
 struct LigandMetaVisitor;
@@ -55,7 +57,7 @@ impl Serialize for AlleleMeta {
     where
         S: Serializer,
     {
-        let mut allele_meta = serializer.serialize_struct("LigandMeta", 6)?;
+        let mut allele_meta = serializer.serialize_struct("LigandMeta", 8)?;
         allele_meta.serialize_field("allele", &self.allele.to_string())?;
         allele_meta.serialize_field("netmhcpan_nn", &self.netmhcpan_nn.to_string())?;
         allele_meta.serialize_field(
@@ -81,6 +83,11 @@ impl Serialize for AlleleMeta {
             allele_meta.serialize_field("kir_ligand_motif", "NA")?;
             allele_meta.serialize_field("kir_ligand_freq", "NA")?;
         }
+        allele_meta.serialize_field(
+            "kir_ligand_ambiguous",
+            if self.ligand_ambiguous { "1" } else { "0" },
+        )?;
+        allele_meta.serialize_field("ligand_table", &self.ligand_table)?;
         allele_meta.end()
     }
 }
@@ -104,39 +111,86 @@ where
     Ok(s.parse::<LigandMotif>().ok())
 }
 
-pub fn optional_bool_serialize<S>(x: Option<bool>, s: S) -> Result<S::Ok, S::Error>
+/// Reads a KIR column holding either presence/absence, the copy number (e.g. `2`) or the typed
+/// alleles separated by '+', '/', ',' or ';' (e.g. `001+004` or `KIR3DL1*001/KIR3DL1*004`)
+pub fn optional_kir_typing_deserialize<'de, D>(
+    deserializer: D,
+) -> Result<Option<KirTyping>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s: &str = Deserialize::deserialize(deserializer)?;
+
+    match s.trim() {
+        "1" | "Y" | "T" | "TRUE" => Ok(Some(KirTyping::Presence(true))),
+        "0" | "N" | "F" | "FALSE" => Ok(Some(KirTyping::Presence(false))),
+        "NA" | "Unknown" | "" => Ok(None),
+        s if s.len() <= 2 && s.parse::<u8>().is_ok() => {
+            Ok(s.parse::<u8>().ok().map(KirTyping::Copies))
+        }
+        s => s
+            .split(|c: char| c == '+' || c == '/' || c == ',' || c == ';' || c.is_whitespace())
+            .filter(|allele| !allele.is_empty())
+            .map(|allele| {
+                let allele = allele.rsplit('*').next().unwrap_or(allele);
+                allele.parse::<KirAllele>().map_err(|_| {
+                    de::Error::custom(format!(
+                        "Could not deduce TRUE/FALSE, copy number or KIR alleles from {}",
+                        s
+                    ))
+                })
+            })
+            .collect::<Result<Vec<KirAllele>, D::Error>>()
+            .map(|alleles| Some(KirTyping::Alleles(alleles))),
+    }
+}
+
+pub fn optional_float_serialize<S>(x: &Option<f32>, s: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
     match x {
-        Some(x) => s.serialize_str(if x { "1" } else { "0" }),
+        Some(x) => s.serialize_str(format!("{:.2}", x).as_str()),
         None => s.serialize_str("NA"),
     }
 }
 
-pub fn optional_bool_deserialize<'de, D>(deserializer: D) -> Result<Option<bool>, D::Error>
+pub fn optional_count_serialize<S>(x: &Option<u8>, s: S) -> Result<S::Ok, S::Error>
 where
-    D: Deserializer<'de>,
+    S: Serializer,
 {
-    let s: &str = Deserialize::deserialize(deserializer)?;
+    match x {
+        Some(x) => s.serialize_str(x.to_string().as_str()),
+        None => s.serialize_str("NA"),
+    }
+}
 
-    match s {
-        "1" | "Y" | "T" | "TRUE" => Ok(Some(true)),
-        "0" | "N" | "F" | "FALSE" => Ok(Some(false)),
-        "NA" | "Unknown" => Ok(None),
-        s => Err(de::Error::custom(format!(
-            "Could not deduce TRUE/FALSE from {}",
-            s
-        ))),
+pub fn optional_display_serialize<S, T>(x: &Option<T>, s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    T: std::fmt::Display,
+{
+    match x {
+        Some(x) => s.serialize_str(x.to_string().as_str()),
+        None => s.serialize_str("NA"),
     }
 }
 
-pub fn optional_float_serialize<S>(x: &Option<f32>, s: S) -> Result<S::Ok, S::Error>
+pub fn optional_value_serialize<S, T>(x: &Option<T>, s: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
+    T: Serialize,
 {
     match x {
-        Some(x) => s.serialize_str(format!("{:.2}", x).as_str()),
+        Some(x) => x.serialize(s),
         None => s.serialize_str("NA"),
     }
 }
+
+pub fn display_serialize<S, T>(x: &T, s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    T: std::fmt::Display,
+{
+    s.serialize_str(x.to_string().as_str())
+}
@@ -1,75 +1,88 @@
 use crate::calc::{CalcFsResult, CohortResult};
 use crate::error::Error;
-use crate::meta::{AlleleMeta, BindingMeta};
+use crate::ligand::LigandDiff;
+use crate::meta::{AlleleMeta, AmbiguousLigandMeta, BindingMeta, EducationMeta};
+use crate::receptor::{LilrbResult, ReceptorResult};
+use crate::shard::ShardInfo;
 
-use immunoprot::ig_like::kir_ligand::{KirLigandMap, IPD_KIR_URL};
+use immunoprot::ig_like::kir_ligand::KirLigandMap;
 use immunoprot::mhc::hla::ClassI;
 use log::warn;
 
-/// Writes ligand information to the global file
-pub(crate) fn write_project_ligand_info(kir_ligand: &KirLigandMap) {
+/// Writes ligand information to a table file, `source` is recorded in the header comment
+pub(crate) fn write_ligand_table(
+    kir_ligand: &KirLigandMap,
+    source: &str,
+    path: &std::path::Path,
+) -> std::result::Result<(), Error> {
     let dt_now = chrono::Utc::now().format("%Y-%m-%d");
-    let comment_line = format!("# Obtained from {} on {}", IPD_KIR_URL, dt_now);
+    let comment_line = format!("# Obtained from {} on {}", source, dt_now);
 
-    let mut alleles = kir_ligand.alleles.iter().collect::<Vec<&ClassI>>();
+    let mut alleles = kir_ligand.alleles().iter().collect::<Vec<&ClassI>>();
     alleles.sort();
 
-    if let Some(project_dir) = directories::ProjectDirs::from("", "", crate::TOOL_NAME) {
-        if !project_dir.data_dir().exists() {
-            std::fs::create_dir(project_dir.data_dir()).unwrap_or_else(|_| {
-                warn!(
-                    "Could not create project cache directory {}",
-                    project_dir.data_dir().display()
-                )
-            });
-        }
+    let mut wrt = csv::WriterBuilder::new()
+        .delimiter(b'\t')
+        .has_headers(false)
+        .flexible(true)
+        .from_path(path)
+        .map_err(|_| Error::CouldNotWriteLigandTable(path.to_path_buf()))?;
 
-        let path = project_dir.data_dir().join(crate::PROJECT_LIGAND_TABLE);
+    match wrt.write_record(&[comment_line]) {
+        Ok(_) => (),
+        Err(_) => warn!("Could not write download date time information to ligand file"),
+    }
 
-        let wrt = csv::WriterBuilder::new()
-            .delimiter(b'\t')
-            .has_headers(false)
-            .from_path(path.to_path_buf());
+    alleles.iter().for_each(|allele| {
+        if let Some(info) = kir_ligand.info(allele) {
+            let record = vec![
+                info.allele().to_string(),
+                info.motif().to_string(),
+                info.freq().to_string(),
+            ];
 
-        if let Ok(mut wrt) = wrt {
-            match wrt.write_record(&vec![comment_line, "".to_string(), "".to_string()]) {
+            match wrt.write_record(&record) {
                 Ok(_) => (),
-                Err(_) => warn!("Could not write download date time information to ligand file"),
+                Err(_) => warn!(
+                    "Could not write kir ligand information for allele {}",
+                    allele.to_nomenclature_string()
+                ),
             }
-
-            alleles.iter().for_each(|allele| {
-                if let Some(info) = kir_ligand.cache.get(allele) {
-                    let record = vec![
-                        info.allele().to_string(),
-                        info.motif().to_string(),
-                        info.freq().to_string(),
-                    ];
-
-                    match wrt.write_record(&record) {
-                        Ok(_) => (),
-                        Err(_) => warn!(
-                            "Could not write kir ligand information for allele {}",
-                            allele.to_nomenclature_string()
-                        ),
-                    }
-                }
-            })
-        } else {
-            warn!(
-                "Could not open kir ligand information file located at {}",
-                path.display()
-            )
         }
-    } else {
-        warn!("Could not access global project directory for this OS")
-    }
+    });
+
+    wrt.flush()
+        .map_err(|_| Error::CouldNotWriteLigandTable(path.to_path_buf()))
+}
+
+/// Writes alleles whose motif differs between two kir ligand table versions as TSV
+pub(crate) fn write_ligand_diff<W>(diff: &[LigandDiff], wtr: W) -> std::result::Result<(), Error>
+where
+    W: std::io::Write,
+{
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(true)
+        .delimiter(b'\t')
+        .from_writer(wtr);
+
+    diff.iter()
+        .map(|entry| wtr.serialize(entry))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| Error::CouldNotWriteLigandDiff)?;
+
+    wtr.flush().map_err(|_| Error::CouldNotWriteLigandDiff)
 }
 
 pub struct OutputWriters {
     pub allele_meta: csv::Writer<std::fs::File>,
     pub binding_meta: csv::Writer<std::fs::File>,
-    pub allele_fs_result: csv::Writer<std::fs::File>,
+    pub allele_fs_result: Option<csv::Writer<std::fs::File>>,
     pub cohort_result: Option<csv::Writer<std::fs::File>>,
+    pub ambiguous_ligands: csv::Writer<std::fs::File>,
+    pub nk_education: Option<csv::Writer<std::fs::File>>,
+    pub receptor_result: Option<csv::Writer<std::fs::File>>,
+    pub lilrb_result: Option<csv::Writer<std::fs::File>>,
+    pub shard_info: Option<csv::Writer<std::fs::File>>,
 }
 
 impl OutputWriters {
@@ -82,31 +95,116 @@ impl OutputWriters {
             .map(|meta| self.allele_meta.serialize(meta))
             .collect::<Result<Vec<_>, _>>();
 
-        Ok(write_result.or_else(|_| Err(Error::CouldNotWriteAlleleMeta))?)
+        write_result.map_err(|_| Error::CouldNotWriteAlleleMeta)
     }
 
-    pub fn write_binding_meta(
+    pub fn write_ambiguous_ligands(
         &mut self,
-        metadata: &[BindingMeta],
+        metadata: &[AmbiguousLigandMeta],
     ) -> std::result::Result<Vec<()>, Error> {
         let write_result = metadata
             .iter()
-            .map(|meta| self.binding_meta.serialize(meta))
+            .map(|meta| self.ambiguous_ligands.serialize(meta))
             .collect::<Result<Vec<_>, _>>();
 
-        Ok(write_result.or_else(|_| Err(Error::CouldNotWriteBindingMeta))?)
+        write_result.map_err(|_| Error::CouldNotWriteAmbiguousLigands)
+    }
+
+    pub fn write_nk_education(
+        &mut self,
+        metadata: &[EducationMeta],
+    ) -> std::result::Result<Vec<()>, Error> {
+        if let Some(ref mut nk_education_file) = self.nk_education {
+            let write_result = metadata
+                .iter()
+                .map(|meta| nk_education_file.serialize(meta))
+                .collect::<Result<Vec<_>, _>>();
+            write_result.map_err(|_| Error::CouldNotWriteNkEducation)
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    pub fn write_shard_info(&mut self, shard_info: &ShardInfo) -> std::result::Result<(), Error> {
+        if let Some(ref mut shard_info_file) = self.shard_info {
+            shard_info_file
+                .serialize(shard_info)
+                .map_err(|_| Error::CouldNotWriteShardInfo)?;
+        }
+
+        Ok(())
     }
 
-    pub fn write_fs_result(
+    pub fn write_lilrb_result(
         &mut self,
-        fs_results: &[CalcFsResult],
+        lilrb_results: &[LilrbResult],
     ) -> std::result::Result<Vec<()>, Error> {
-        let write_result = fs_results
+        if let Some(ref mut lilrb_file) = self.lilrb_result {
+            let write_result = lilrb_results
+                .iter()
+                .map(|result| lilrb_file.serialize(result))
+                .collect::<Result<Vec<_>, _>>();
+            write_result.map_err(|_| Error::CouldNotWriteLilrbResult)
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    /// Writes a column for every receptor, the header is created from the receptor names
+    pub fn write_receptor_result(
+        &mut self,
+        receptors: &[&str],
+        receptor_results: &[ReceptorResult],
+    ) -> std::result::Result<(), Error> {
+        if let Some(ref mut receptor_file) = self.receptor_result {
+            let header = ["index", "id"]
+                .iter()
+                .chain(receptors.iter())
+                .chain(["ligand_table"].iter());
+            receptor_file
+                .write_record(header)
+                .map_err(|_| Error::CouldNotWriteReceptorResult)?;
+
+            receptor_results
+                .iter()
+                .map(|result| {
+                    let scores = result.scores.iter().map(|score| match score {
+                        Some(score) => format!("{:.2}", score),
+                        None => "NA".to_string(),
+                    });
+                    let record = vec![result.index.to_string(), result.id.to_string()]
+                        .into_iter()
+                        .chain(scores)
+                        .chain(std::iter::once(result.ligand_table.to_string()));
+                    receptor_file.write_record(record)
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| Error::CouldNotWriteReceptorResult)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_binding_meta(
+        &mut self,
+        metadata: &[BindingMeta],
+    ) -> std::result::Result<Vec<()>, Error> {
+        let write_result = metadata
             .iter()
-            .map(|result| self.allele_fs_result.serialize(result))
+            .map(|meta| self.binding_meta.serialize(meta))
             .collect::<Result<Vec<_>, _>>();
 
-        Ok(write_result.or_else(|_| Err(Error::CouldNotWriteFsResult))?)
+        write_result.map_err(|_| Error::CouldNotWriteBindingMeta)
+    }
+
+    /// Writes a single FS result as results are streamed from the calculations
+    pub fn write_fs_result(&mut self, fs_result: &CalcFsResult) -> std::result::Result<(), Error> {
+        match self.allele_fs_result {
+            Some(ref mut allele_fs_file) => allele_fs_file
+                .serialize(fs_result)
+                .map_err(|_| Error::CouldNotWriteFsResult),
+            _ => Ok(()),
+        }
     }
 
     pub fn write_cohort_result(
@@ -118,7 +216,7 @@ impl OutputWriters {
                 .iter()
                 .map(|result| cohort_result_file.serialize(result))
                 .collect::<Result<Vec<_>, _>>();
-            Ok(write_result.or_else(|_| Err(Error::CouldNotWriteFsResult))?)
+            write_result.map_err(|_| Error::CouldNotWriteCohortResult)
         } else {
             Ok(Vec::new())
         }
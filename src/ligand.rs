@@ -0,0 +1,309 @@
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+use crate::io::writer::write_ligand_table;
+
+use immunoprot::ig_like::kir_ligand::{KirLigandMap, LigandMotif};
+use immunoprot::mhc::hla::ClassI;
+use log::{info, warn};
+use serde::Serialize;
+
+/// Name under which the table shipped with the tool is listed
+pub const BUNDLED_VERSION: &str = "bundled";
+/// File in the data directory storing the name of the active table version
+pub const ACTIVE_VERSION_FILE: &str = "active_ligand_table";
+/// Version name of the table `--update` stored in the data directory before versions were kept
+pub const LEGACY_VERSION: &str = "allele_motifs";
+const TABLE_EXTENSION: &str = "tsv";
+
+/// A stored kir ligand table and the comment describing where it was obtained from
+#[derive(Debug)]
+pub struct LigandVersion {
+    pub name: String,
+    pub provenance: String,
+}
+
+/// Kir ligand table versions kept in the global data directory, each version is a TSV file named
+/// after the version
+#[derive(Debug)]
+pub struct LigandStore {
+    dir: PathBuf,
+}
+
+impl LigandStore {
+    pub fn new<P>(dir: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Opens the store located in the global data directory for this OS
+    pub fn open() -> Result<Self, Error> {
+        directories::ProjectDirs::from("", "", crate::TOOL_NAME)
+            .map(|project_dir| LigandStore::new(project_dir.data_dir()))
+            .ok_or(Error::NoGlobalConfigDir)
+    }
+
+    /// Whether the store holds a table updated before versions were kept and no active version
+    pub fn has_legacy(&self) -> bool {
+        !self.dir.join(ACTIVE_VERSION_FILE).exists() && self.contains(LEGACY_VERSION)
+    }
+
+    /// Makes a table updated before versions were kept the active version, so it is used instead
+    /// of the bundled table. Returns whether there was a table to migrate
+    pub fn migrate_legacy(&self) -> Result<bool, Error> {
+        if !self.has_legacy() {
+            return Ok(false);
+        }
+
+        self.set_active(LEGACY_VERSION)?;
+        Ok(true)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn table_path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", name, TABLE_EXTENSION))
+    }
+
+    /// Lists the bundled table followed by the stored versions sorted by name
+    pub fn versions(&self) -> Vec<LigandVersion> {
+        let mut versions = std::fs::read_dir(&self.dir)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .map(|entry| entry.path())
+                    .filter(|path| {
+                        path.extension().and_then(|ext| ext.to_str()) == Some(TABLE_EXTENSION)
+                    })
+                    .filter_map(|path| {
+                        let name = path.file_stem()?.to_str()?.to_string();
+                        let provenance = std::fs::read_to_string(&path)
+                            .map(|table| table_provenance(&table))
+                            .unwrap_or_default();
+
+                        Some(LigandVersion { name, provenance })
+                    })
+                    .collect::<Vec<LigandVersion>>()
+            })
+            .unwrap_or_default();
+
+        versions.sort_by(|a, b| a.name.cmp(&b.name));
+        versions.insert(
+            0,
+            LigandVersion {
+                name: BUNDLED_VERSION.to_string(),
+                provenance: table_provenance(immunoprot::LIGAND_MAP_DEF),
+            },
+        );
+
+        versions
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        name == BUNDLED_VERSION || self.table_path(name).exists()
+    }
+
+    /// Name of the version used when none is requested, falls back to the bundled table
+    pub fn active(&self) -> String {
+        std::fs::read_to_string(self.dir.join(ACTIVE_VERSION_FILE))
+            .ok()
+            .map(|name| name.trim().to_string())
+            .filter(|name| self.contains(name))
+            .unwrap_or_else(|| BUNDLED_VERSION.to_string())
+    }
+
+    pub fn set_active(&self, name: &str) -> Result<(), Error> {
+        if !self.contains(name) {
+            return Err(Error::UnknownLigandVersion(name.to_string()));
+        }
+
+        std::fs::create_dir_all(&self.dir).map_err(|_| Error::CouldNotCreateOutputDir)?;
+        std::fs::write(self.dir.join(ACTIVE_VERSION_FILE), name)
+            .map_err(|_| Error::CouldNotSetActiveLigandVersion(name.to_string()))
+    }
+
+    /// Loads the version, labelling the map with the version name
+    pub fn load(&self, name: &str) -> Result<KirLigandMap, Error> {
+        if name == BUNDLED_VERSION {
+            return KirLigandMap::init()
+                .map(|map| map.with_version(BUNDLED_VERSION))
+                .map_err(|_| Error::CouldNotLoadKirLigandMap);
+        }
+
+        let path = self.table_path(name);
+        if !path.exists() {
+            return Err(Error::UnknownLigandVersion(name.to_string()));
+        }
+
+        KirLigandMap::from_path(&path)
+            .map(|map| map.with_version(name))
+            .map_err(|e| Error::CouldNotReadLigandFile(path, e))
+    }
+
+    /// Stores the table as a new version and returns its name, the name defaults to the current
+    /// date with a numeric suffix when a version for the date already exists
+    pub fn save(
+        &self,
+        kir_ligand: &KirLigandMap,
+        name: Option<&str>,
+        source: &str,
+    ) -> Result<String, Error> {
+        let name = match name {
+            Some(name) => {
+                if name == BUNDLED_VERSION || name.is_empty() || name.contains(is_path_char) {
+                    return Err(Error::InvalidLigandVersionName(name.to_string()));
+                }
+                if self.contains(name) {
+                    warn!("Overwriting kir ligand table version '{}'", name);
+                }
+                name.to_string()
+            }
+            _ => self.dated_name(),
+        };
+
+        std::fs::create_dir_all(&self.dir).map_err(|_| Error::CouldNotCreateOutputDir)?;
+        write_ligand_table(kir_ligand, source, &self.table_path(&name))?;
+        info!("Stored kir ligand table version '{}'", name);
+
+        Ok(name)
+    }
+
+    fn dated_name(&self) -> String {
+        let date = chrono::Utc::now().format("%Y-%m-%d").to_string();
+
+        (1..)
+            .map(|n| {
+                if n == 1 {
+                    date.clone()
+                } else {
+                    format!("{}-{}", date, n)
+                }
+            })
+            .find(|name| !self.contains(name))
+            .unwrap_or(date)
+    }
+}
+
+fn is_path_char(c: char) -> bool {
+    c == '/' || c == '\\' || c == '.'
+}
+
+/// First comment line of a table, describing where it was obtained from
+fn table_provenance(table: &str) -> String {
+    table
+        .lines()
+        .next()
+        .filter(|line| line.starts_with('#'))
+        .map(|line| line.trim_start_matches('#').trim().to_string())
+        .unwrap_or_default()
+}
+
+/// An allele whose motif differs between two table versions, absent alleles have no motif
+#[derive(Debug, Serialize, PartialEq)]
+pub struct LigandDiff {
+    #[serde(with = "serde_with::rust::display_fromstr")]
+    pub allele: ClassI,
+    #[serde(serialize_with = "crate::io::ser::optional_motif_serialize")]
+    pub from_motif: Option<LigandMotif>,
+    #[serde(serialize_with = "crate::io::ser::optional_motif_serialize")]
+    pub to_motif: Option<LigandMotif>,
+}
+
+/// Lists alleles whose motif changed, was added or was removed between the two tables
+pub fn diff_ligand_maps(from: &KirLigandMap, to: &KirLigandMap) -> Vec<LigandDiff> {
+    let mut alleles = from.alleles().union(to.alleles()).collect::<Vec<&ClassI>>();
+    alleles.sort();
+
+    alleles
+        .into_iter()
+        .filter_map(|allele| {
+            let from_motif = from.info(allele).map(|info| info.motif().clone());
+            let to_motif = to.info(allele).map(|info| info.motif().clone());
+
+            if from_motif != to_motif {
+                Some(LigandDiff {
+                    allele: allele.clone(),
+                    from_motif,
+                    to_motif,
+                })
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use immunoprot::ig_like::kir_ligand::{AlleleFreq, KirLigandInfo};
+
+    #[test]
+    fn test_store_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LigandStore::new(dir.path());
+        let mut updated = KirLigandMap::init().unwrap();
+        let allele = "A*01:01:01:01".parse::<ClassI>().unwrap();
+
+        updated.insert_info(KirLigandInfo::new(
+            allele.clone(),
+            LigandMotif::Bw4_80I,
+            AlleleFreq::Common,
+        ));
+
+        let name = store.save(&updated, Some("edited"), "test").unwrap();
+        assert_eq!(store.active(), BUNDLED_VERSION);
+
+        store.set_active(&name).unwrap();
+        assert_eq!(store.active(), "edited");
+        assert!(store.set_active("missing").is_err());
+        assert!(store.save(&updated, Some("../edited"), "test").is_err());
+
+        let versions = store.versions();
+        assert_eq!(versions.len(), 2);
+        assert!(versions[1].provenance.starts_with("Obtained from test"));
+
+        let bundled = store.load(BUNDLED_VERSION).unwrap();
+        let edited = store.load("edited").unwrap();
+        assert_eq!(edited.version(), Some("edited"));
+        assert_eq!(
+            diff_ligand_maps(&bundled, &edited),
+            vec![LigandDiff {
+                allele,
+                from_motif: Some(LigandMotif::Unclassified),
+                to_motif: Some(LigandMotif::Bw4_80I),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_migrate_legacy() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LigandStore::new(dir.path());
+        let updated = KirLigandMap::init().unwrap();
+
+        assert!(!store.migrate_legacy().unwrap());
+        assert_eq!(store.active(), BUNDLED_VERSION);
+
+        store
+            .save(&updated, Some(LEGACY_VERSION), "legacy update")
+            .unwrap();
+        assert!(store.has_legacy());
+        assert_eq!(store.active(), BUNDLED_VERSION);
+        assert!(store.migrate_legacy().unwrap());
+        assert!(!store.has_legacy());
+        assert_eq!(store.active(), LEGACY_VERSION);
+        assert!(store.load(LEGACY_VERSION).is_ok());
+
+        // An explicitly chosen version is kept
+        store.set_active(BUNDLED_VERSION).unwrap();
+        assert!(!store.migrate_legacy().unwrap());
+        assert_eq!(store.active(), BUNDLED_VERSION);
+    }
+}
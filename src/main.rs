@@ -1,31 +1,57 @@
 // TODO: If binding predicitons file does not exist the error is not good;
 // #![warn(missing_debug_implementations, rust_2018_idioms, missing_docs)]
-#![allow(dead_code, unused_variables)]
 mod calc;
 mod cli;
 mod cohort;
 mod error;
 mod io;
+mod ligand;
 mod meta;
+mod receptor;
+mod shard;
+#[cfg(test)]
+mod test_utils;
 
 pub static KIR_MOTIF_BINDING: &str = include_str!("resources/kir_motif_binding.tsv");
 pub static LILRB_SIMSCORES: &str = include_str!("resources/lilrb_simscores.tsv");
 pub static KIR_SIMSCORES: &str = include_str!("resources/kir_ligand_simscores.tsv");
-pub const LIGAND_TABLE: &str = include_str!("resources/allele_motifs.tsv");
-pub const PROJECT_LIGAND_TABLE: &str = "allele_motifs.tsv";
+pub static KIR_ALLOTYPES: &str = include_str!("resources/kir_allotypes.tsv");
+pub static KIR_PEPTIDE_RULES: &str = include_str!("resources/kir_peptide_rules.tsv");
 pub const TOOL_NAME: &str = "fs-tool";
 pub const KIR_DEF: &str = "KIR:2,7,8,9";
 pub const TCR_DEF: &str = "TCR:2,3,4,5,6,9";
 pub const LOGGING_MODULES: [&str; 3] = ["immunoprot", "netmhcpan", "fs-tool"];
 pub const DEFAULT_DELIM: u8 = b',';
+pub const FS_CHANNEL_BOUND: usize = 1024;
 
-use crate::calc::{calculate_fs, calculate_index_cohort_fs, create_calc_combs, IndexCache};
-use crate::cli::{get_measures, print_defaults, Opt};
+use crate::calc::{
+    allele_pairs, calculate_fs, calculate_index_cohort_fs, cohort_allele_pairs, AlleleCutoffs,
+    BindingWeight, Bootstrap, FsOptions, HashedMotifs, IndexCache, KirReference, NullModel,
+};
+use crate::cli::{
+    get_kir_engaging_measures, get_measures, print_defaults, set_logging, Command, Opt,
+};
 use crate::cohort::Individual;
-use crate::io::reader::{read_kir_motif_binding, read_lilrb_scores, read_temp_cohort};
-use crate::meta::{create_allele_metadata, create_binding_metadata};
+use crate::error::Error;
+use crate::io::reader::{
+    read_allele_thresholds, read_kir_allotypes, read_kir_binding_table, read_kir_ligand_scores,
+    read_kir_motif_binding, read_kir_peptide_rules, read_kir_peptide_rules_table,
+    read_lilrb_scores, read_lilrb_table, read_receptor_tables, read_temp_cohort,
+};
+use crate::meta::{
+    create_allele_metadata, create_ambiguous_ligand_metadata, create_binding_metadata,
+    create_education_metadata,
+};
+use crate::receptor::{
+    calculate_lilrb_scores, calculate_receptor_scores, LilrbOptions, LilrbReceptor,
+    ReceptorInteraction, ReceptorTable,
+};
+use crate::shard::{input_files, ShardInfo};
 
+use immunoprot::mhc::hla::ClassI;
+use log::info;
 use netmhcpan::reader::read_raw_netmhcpan;
+use std::collections::{HashMap, HashSet};
 use structopt::StructOpt;
 
 fn main() -> std::result::Result<(), ()> {
@@ -45,6 +71,14 @@ fn main_try() -> Result<(), Box<dyn std::error::Error>> {
         return print_defaults();
     }
 
+    if matches.subcommand_name().is_some() {
+        set_logging(
+            matches.is_present("quiet"),
+            matches.occurrences_of("verbose"),
+        );
+        return Ok(Command::from_clap(&matches).run()?);
+    }
+
     let opt = Opt::from_args();
     opt.set_logging();
 
@@ -52,53 +86,277 @@ fn main_try() -> Result<(), Box<dyn std::error::Error>> {
         .num_threads(opt.threads)
         .build_global()?;
 
+    if opt.binding_weight == BindingWeight::Affinity && !opt.threshold_sweep.is_empty() {
+        return Err(Error::AffinityThresholdSweep.into());
+    }
+
     let kir_ligand_map = opt.setup_kir_ligand_info()?;
     let mut output_writers = opt.output_writers()?;
 
+    let binding_predictions = input_files(&opt.binding_predictions);
     let binding_data = read_raw_netmhcpan(opt.binding_predictions)?;
 
-    let allele_meta = create_allele_metadata(&binding_data, &kir_ligand_map);
+    let allele_meta = create_allele_metadata(&binding_data, &kir_ligand_map, opt.ligand_ambiguity);
     let binding_meta = create_binding_metadata(&binding_data);
 
     output_writers.write_allele_meta(&allele_meta)?;
     output_writers.write_binding_meta(&binding_meta)?;
 
-    let allele_combs = create_calc_combs(&binding_data);
-    let measures = get_measures(opt.measure, opt.drop_default);
+    let mut measures = get_measures(opt.measure, opt.drop_default);
+    if opt.kir_engaging {
+        let peptide_rules = match &opt.kir_peptide_rules {
+            Some(path) => read_kir_peptide_rules_table(path)?,
+            _ => read_kir_peptide_rules(),
+        };
+        measures.extend(get_kir_engaging_measures(&peptide_rules));
+    }
 
-    let fs_result = calculate_fs(
-        &allele_combs,
-        &measures,
-        &kir_ligand_map,
-        &opt.peptide_length,
-        binding_data.weak_threshold(),
-        opt.unique,
-    );
+    let cohort = match &opt.cohort {
+        Some(cohort_path) => read_temp_cohort(cohort_path)?
+            .into_iter()
+            .map(Individual::from)
+            .collect::<Vec<Individual>>(),
+        _ => Vec::new(),
+    };
 
-    output_writers.write_fs_result(&fs_result)?;
+    let index_alleles = opt.index.clone().unwrap_or_default();
+    let (mut pair_index_alleles, mut pair_non_index_alleles) = if opt.cohort_pairs {
+        cohort_allele_pairs(&binding_data, &index_alleles, &cohort)
+    } else {
+        (binding_data.list_alleles(), binding_data.list_alleles())
+    };
+    // The binding predictions list alleles in no particular order, sorting them keeps the order of
+    // the FS results and the pairs shards select by position the same in every run
+    pair_index_alleles.sort_unstable();
+    pair_non_index_alleles.sort_unstable();
+    let pair_alleles = pair_index_alleles
+        .iter()
+        .chain(pair_non_index_alleles.iter())
+        .copied()
+        .collect::<HashSet<&ClassI>>();
 
-    if let (Some(index_alleles), Some(cohort_path)) = (opt.index, opt.cohort) {
-        let cohort = read_temp_cohort(cohort_path)?
+    let hashed_motifs =
+        HashedMotifs::new(&binding_data, &pair_alleles, &measures, &opt.peptide_length);
+    if opt.binding_weight == BindingWeight::Affinity && !hashed_motifs.has_affinity() {
+        return Err(Error::MissingAffinity.into());
+    }
+    let allele_cutoffs = match &opt.allele_thresholds {
+        Some(path) => read_allele_thresholds(path)?
             .into_iter()
-            .map(Individual::from)
-            .collect::<Vec<Individual>>();
-
-        let kir_motif_interactions = read_kir_motif_binding();
-        let index_fs_cache = IndexCache::new(
-            index_alleles,
-            fs_result,
-            &kir_motif_interactions,
-            &measures,
-            &opt.peptide_length,
+            .map(|(allele, threshold)| (allele, threshold.cutoff(&binding_data)))
+            .collect(),
+        _ => HashMap::new(),
+    };
+    // Without a sweep only the binding threshold (and allele thresholds) of the run are used
+    let thresholds = match opt.threshold_sweep.as_slice() {
+        [] => vec![opt.binding_threshold],
+        sweep => sweep.to_vec(),
+    }
+    .into_iter()
+    .map(|threshold| AlleleCutoffs::new(threshold.cutoff(&binding_data), allele_cutoffs.clone()))
+    .collect::<Vec<AlleleCutoffs>>();
+
+    if let Some(shard) = opt.shard {
+        let shard_info = ShardInfo {
+            shard: shard.index,
+            shards: shard.count,
+            pairs: shard
+                .select(allele_pairs(
+                    pair_index_alleles.clone(),
+                    pair_non_index_alleles.clone(),
+                ))
+                .count(),
+            alleles: pair_index_alleles.len(),
+            binding_predictions,
+            measures: measures
+                .iter()
+                .map(|measure| format!("{}:{:?}", measure.name, measure.motif_pos))
+                .collect::<Vec<String>>()
+                .join(";"),
+            peptide_lengths: format!("{:?}", opt.peptide_length),
+            threshold: thresholds
+                .iter()
+                .map(|cutoffs| cutoffs.default_cutoff().to_string())
+                .collect::<Vec<String>>()
+                .join(";"),
+            allele_thresholds: input_files(opt.allele_thresholds.as_slice()),
+            binding_weight: opt.binding_weight.to_string(),
+            bootstrap: match opt.bootstrap {
+                Some(replicates) => format!(
+                    "{} replicates, seed {}, level {}",
+                    replicates, opt.seed, opt.confidence_level
+                ),
+                _ => "NA".to_string(),
+            },
+            null_model: match opt.null_replicates {
+                Some(replicates) => format!("{} replicates, seed {}", replicates, opt.seed),
+                _ => "NA".to_string(),
+            },
+            unique: opt.unique,
+            ligand_table: kir_ligand_map.version().unwrap_or("NA").to_string(),
+            ligand_ambiguity: opt.ligand_ambiguity.to_string(),
+            overlap_metrics: opt
+                .overlap_metric
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<String>>()
+                .join(";"),
+            skip_allele_fs: opt.skip_allele_fs,
+        };
+        info!(
+            "Calculating shard {} of {} ({} allele pairs)",
+            shard.index, shard.count, shard_info.pairs
         );
-        let lilrb_scores = read_lilrb_scores();
-        let cohort_result = calculate_index_cohort_fs(
-            index_fs_cache,
+        output_writers.write_shard_info(&shard_info)?;
+    }
+
+    let ligand_policy = opt.ligand_ambiguity;
+    let fs_options = FsOptions {
+        pep_lengths: opt.peptide_length.clone(),
+        bootstrap: match opt.bootstrap {
+            Some(replicates) => Some(Bootstrap::new(replicates, opt.seed, opt.confidence_level)?),
+            _ => None,
+        },
+        null_model: match opt.null_replicates {
+            Some(replicates) => Some(NullModel::new(replicates, opt.seed, &binding_data)?),
+            _ => None,
+        },
+    };
+    let overlap_metrics = &opt.overlap_metric;
+    let mut index_fs_results = Vec::new();
+    for cutoffs in thresholds.iter() {
+        if !opt.threshold_sweep.is_empty() {
+            info!(
+                "Calculating FS with a binding threshold of {}",
+                cutoffs.default_cutoff()
+            );
+        }
+        let motif_sets = hashed_motifs.motif_sets((cutoffs, opt.binding_weight), opt.unique);
+        let (sender, receiver) = std::sync::mpsc::sync_channel(FS_CHANNEL_BOUND);
+        let pairs = allele_pairs(pair_index_alleles.clone(), pair_non_index_alleles.clone());
+        let pairs: Box<dyn Iterator<Item = (&ClassI, &ClassI)> + Send> = match opt.shard {
+            Some(shard) => Box::new(shard.select(pairs)),
+            _ => Box::new(pairs),
+        };
+        let fs_result = std::thread::scope(|scope| {
+            scope.spawn(|| {
+                calculate_fs(
+                    pairs,
+                    &motif_sets,
+                    &measures,
+                    &kir_ligand_map,
+                    ligand_policy,
+                    &fs_options,
+                    sender,
+                )
+            });
+
+            // Only the results of the index alleles are kept for the cohort calculations
+            receiver
+                .into_iter()
+                .try_fold(Vec::new(), |mut index_fs_result, result| {
+                    let result = result.with_metrics(overlap_metrics);
+                    output_writers.write_fs_result(&result)?;
+                    if index_alleles.contains(&result.index) {
+                        index_fs_result.push(result);
+                    }
+                    Ok::<_, Error>(index_fs_result)
+                })
+        })?;
+        index_fs_results.push(fs_result);
+    }
+
+    let run_alleles = binding_data.list_alleles().into_iter().chain(
+        cohort
+            .iter()
+            .flat_map(|individual| &individual.hla_genotype),
+    );
+    let ambiguous_ligands =
+        create_ambiguous_ligand_metadata(run_alleles, &kir_ligand_map, opt.ligand_ambiguity);
+    output_writers.write_ambiguous_ligands(&ambiguous_ligands)?;
+
+    let kir_reference = KirReference::new(
+        match &opt.kir_binding {
+            Some(path) => read_kir_binding_table(path)?,
+            _ => read_kir_motif_binding(),
+        },
+        read_kir_allotypes(),
+        read_kir_ligand_scores(),
+    );
+    let nk_education = create_education_metadata(
+        &cohort,
+        &kir_reference,
+        &kir_ligand_map,
+        opt.ligand_ambiguity,
+    );
+    output_writers.write_nk_education(&nk_education)?;
+
+    if opt.index.is_some() {
+        let receptor_tables = opt
+            .receptor_table
+            .iter()
+            .map(read_receptor_tables)
+            .collect::<Result<Vec<Vec<ReceptorTable>>, _>>()?
+            .into_iter()
+            .flatten()
+            .collect::<Vec<ReceptorTable>>();
+        // The KIR ligand score is reported once per individual and index allele with the receptors
+        let receptors = std::iter::once(&kir_reference.ligand_scores as &dyn ReceptorInteraction)
+            .chain(
+                receptor_tables
+                    .iter()
+                    .map(|table| table as &dyn ReceptorInteraction),
+            )
+            .collect::<Vec<&dyn ReceptorInteraction>>();
+        let receptor_result = calculate_receptor_scores(
+            &index_alleles,
             &cohort,
-            &kir_motif_interactions,
-            &lilrb_scores,
+            &receptors,
+            &kir_ligand_map,
+            opt.ligand_ambiguity,
         );
-        output_writers.write_cohort_result(&cohort_result)?;
+        let receptor_names = receptors
+            .iter()
+            .map(|receptor| receptor.receptor())
+            .collect::<Vec<&str>>();
+        output_writers.write_receptor_result(&receptor_names, &receptor_result)?;
+
+        if !opt.skip_lilrb {
+            let lilrb_scores = match &opt.lilrb_table {
+                Some(path) => read_lilrb_table(path)?,
+                _ => read_lilrb_scores(),
+            };
+            let lilrb_options = LilrbOptions {
+                aggregation: opt.lilrb_aggregation,
+                fallback: opt.lilrb_fallback,
+                nearest: binding_data
+                    .list_nn()
+                    .iter()
+                    .map(|nn| {
+                        let (allele, _, nearest) = nn.info();
+                        (allele.to_string(), nearest.clone())
+                    })
+                    .collect(),
+            };
+            let lilrb_receptors = LilrbReceptor::from_scores(lilrb_scores, lilrb_options);
+            let lilrb_result = calculate_lilrb_scores(
+                &index_alleles,
+                &cohort,
+                &lilrb_receptors,
+                kir_ligand_map.version().unwrap_or("NA"),
+            );
+            output_writers.write_lilrb_result(&lilrb_result)?;
+        }
+
+        for fs_result in index_fs_results {
+            let index_fs_cache = IndexCache::new(
+                index_alleles.clone(),
+                fs_result,
+                kir_ligand_map.version().unwrap_or("NA"),
+            );
+            let cohort_result = calculate_index_cohort_fs(index_fs_cache, &cohort, &kir_reference);
+            output_writers.write_cohort_result(&cohort_result)?;
+        }
     }
     Ok(())
 }
@@ -1,11 +1,16 @@
-use immunoprot::ig_like::kir_ligand::{AlleleFreq, KirLigandInfo, KirLigandMap, LigandMotif};
+use crate::calc::KirReference;
+use crate::cohort::Individual;
+
+use immunoprot::ig_like::kir_ligand::{
+    AlleleFreq, AmbiguityPolicy, KirLigandInfo, KirLigandMap, LigandMotif,
+};
 use immunoprot::mhc::hla::ClassI;
 use netmhcpan::result::BindingData;
 
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 
-use log::debug;
+use log::{debug, warn};
 
 #[derive(Debug)]
 pub struct LigandMeta {
@@ -33,6 +38,40 @@ pub struct AlleleMeta {
     pub netmhcpan_nn: ClassI,
     pub netmhcpan_nn_distance: f32,
     pub ligand_meta: Option<LigandMeta>,
+    pub ligand_ambiguous: bool,
+    pub ligand_table: String,
+}
+
+/// Lists one of the distinct motifs of an allele that matched ligand table entries with
+/// different motifs
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AmbiguousLigandMeta {
+    #[serde(with = "serde_with::rust::display_fromstr")]
+    pub allele: ClassI,
+    #[serde(with = "serde_with::rust::display_fromstr")]
+    pub kir_ligand_motif: LigandMotif,
+    pub n_alleles: usize,
+    pub kir_ligand_freqs: String,
+    #[serde(
+        serialize_with = "crate::io::ser::optional_motif_serialize",
+        deserialize_with = "crate::io::ser::optional_motif_deserialize"
+    )]
+    pub resolved_motif: Option<LigandMotif>,
+    #[serde(with = "serde_with::rust::display_fromstr")]
+    pub policy: AmbiguityPolicy,
+    pub ligand_table: String,
+}
+
+/// Inhibitory KIRs of an individual educated by a cognate ligand in their HLA genotype
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EducationMeta {
+    pub id: String,
+    pub n_educated_ikirs: usize,
+    pub educated_ikirs: String,
+    /// Sum of the strongest interaction strength of every educated iKIR with the individual's
+    /// ligand motifs, the count of educated iKIRs when all strengths are 1.0
+    pub ikir_score: f32,
+    pub ligand_table: String,
 }
 
 #[skip_serializing_none]
@@ -50,6 +89,7 @@ pub struct BindingMeta {
 pub fn create_allele_metadata(
     binding_data: &BindingData,
     kir_ligand_map: &KirLigandMap,
+    ligand_policy: AmbiguityPolicy,
 ) -> Vec<AlleleMeta> {
     binding_data
         .list_nn()
@@ -57,24 +97,109 @@ pub fn create_allele_metadata(
         .map(|nn| {
             let (allele, netmhcpan_nn_distance, netmhcpan_nn) = nn.info();
             debug!("Storing metadata information for nearest neighbour with index {}, distance {} and neighbour {}", &allele, &netmhcpan_nn_distance, &netmhcpan_nn);
-            let mut ligand_info = kir_ligand_map.get_allele_info(allele);
-            ligand_info.sort();
+            let ligand_info = kir_ligand_map.lookup(allele);
 
-            let ligand_meta = match ligand_info.iter().next() {
-                Some(ligand_info) => Some(LigandMeta::new(ligand_info)),
-                None => None,
-            };
+            let ligand_meta = ligand_info.resolve(ligand_policy).info().map(LigandMeta::new);
 
             AlleleMeta {
                 allele: allele.clone(),
                 netmhcpan_nn: netmhcpan_nn.clone(),
                 netmhcpan_nn_distance,
                 ligand_meta,
+                ligand_ambiguous: ligand_info.is_ambiguous(),
+                ligand_table: kir_ligand_map.version().unwrap_or("NA").to_string(),
             }
         })
         .collect::<Vec<AlleleMeta>>()
 }
 
+/// Lists the distinct motifs of every allele whose ligand motif is ambiguous
+pub fn create_ambiguous_ligand_metadata<'a, I>(
+    alleles: I,
+    kir_ligand_map: &KirLigandMap,
+    ligand_policy: AmbiguityPolicy,
+) -> Vec<AmbiguousLigandMeta>
+where
+    I: IntoIterator<Item = &'a ClassI>,
+{
+    let mut alleles = alleles.into_iter().collect::<Vec<&ClassI>>();
+    alleles.sort();
+    alleles.dedup();
+
+    alleles
+        .into_iter()
+        .fold(Vec::new(), |mut ambiguous, allele| {
+            let ligand_info = kir_ligand_map.lookup(allele);
+
+            if ligand_info.is_ambiguous() {
+                let resolved = ligand_info.resolve(ligand_policy);
+                if resolved.is_ambiguous() {
+                    warn!(
+                        "Allele '{}' matches ligand alleles with different motifs and is left without a motif",
+                        allele
+                    );
+                } else {
+                    warn!(
+                        "Allele '{}' matches ligand alleles with different motifs, '{}' policy is used",
+                        allele, ligand_policy
+                    );
+                }
+                let resolved_motif = resolved.info().map(|info| info.motif().clone());
+
+                ligand_info.motifs().into_iter().for_each(|support| {
+                    ambiguous.push(AmbiguousLigandMeta {
+                        allele: allele.clone(),
+                        kir_ligand_motif: support.motif,
+                        n_alleles: support.alleles,
+                        kir_ligand_freqs: support
+                            .freqs
+                            .iter()
+                            .map(ToString::to_string)
+                            .collect::<Vec<String>>()
+                            .join(";"),
+                        resolved_motif: resolved_motif.clone(),
+                        policy: ligand_policy,
+                        ligand_table: kir_ligand_map.version().unwrap_or("NA").to_string(),
+                    })
+                });
+            }
+
+            ambiguous
+        })
+}
+
+/// Creates the NK education summary of every individual
+pub fn create_education_metadata(
+    cohort: &[Individual],
+    kir_reference: &KirReference,
+    kir_ligand_map: &KirLigandMap,
+    ligand_policy: AmbiguityPolicy,
+) -> Vec<EducationMeta> {
+    cohort
+        .iter()
+        .map(|individual| {
+            let educated = individual.educated_kirs(
+                &kir_reference.binding,
+                &kir_reference.allotypes,
+                kir_ligand_map,
+                ligand_policy,
+            );
+
+            EducationMeta {
+                id: individual.id.to_string(),
+                n_educated_ikirs: educated.len(),
+                educated_ikirs: educated
+                    .iter()
+                    .map(|(kir, _)| kir.to_string())
+                    .collect::<Vec<String>>()
+                    .join(";"),
+                ikir_score: educated.iter().map(|(_, strength)| strength).sum(),
+                ligand_table: kir_ligand_map.version().unwrap_or("NA").to_string(),
+            }
+        })
+        .collect()
+}
+
 pub fn create_binding_metadata(binding_data: &BindingData) -> Vec<BindingMeta> {
     let mut binding_meta = Vec::<BindingMeta>::new();
     let proteins = binding_data.proteins();
@@ -0,0 +1,692 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::calc::{two_field, KirBindingTable, KirLigandScores, LilrbScore, LilrbScores};
+use crate::cohort::Individual;
+use crate::error::Error;
+
+use immunoprot::ig_like::kir::Kir;
+use immunoprot::ig_like::kir_ligand::{AmbiguityPolicy, KirLigandMap, LigandMotif};
+use immunoprot::mhc::hla::ClassI;
+use log::warn;
+use rayon::prelude::*;
+use serde::Serialize;
+
+/// HLA allele together with its KIR ligand motif
+pub type HlaLigand<'a> = (&'a ClassI, Option<&'a LigandMotif>);
+
+/// Interaction of a receptor with HLA alleles, implemented by the tables loaded from resource or
+/// user files so new receptors can be added as data
+pub trait ReceptorInteraction: std::fmt::Debug + Send + Sync {
+    /// Name of the receptor, used as the output column
+    fn receptor(&self) -> &str;
+
+    /// Weight with which the receptor engages the allele, `None` when the allele is not covered
+    fn engagement(&self, _allele: HlaLigand) -> Option<f32> {
+        None
+    }
+
+    /// Score between the index and a genotype allele, by default the engagement shared by both
+    fn score(&self, index: HlaLigand, allele: HlaLigand) -> Option<f32> {
+        Some(self.engagement(index)?.min(self.engagement(allele)?))
+    }
+
+    /// Combines the scores of the genotype alleles into a single score, the highest by default
+    fn combine(&self, scores: Vec<f32>) -> Option<f32> {
+        scores
+            .into_iter()
+            .max_by(|a, b| a.partial_cmp(b).expect("Tried to compare a NaN"))
+    }
+}
+
+/// Score of the receptor between the index allele and an individual's genotype
+pub fn receptor_score(
+    receptor: &dyn ReceptorInteraction,
+    index: HlaLigand,
+    genotype: &[HlaLigand],
+) -> Option<f32> {
+    receptor.combine(
+        genotype
+            .iter()
+            .filter_map(|allele| receptor.score(index, *allele))
+            .collect(),
+    )
+}
+
+/// Scores of the configured receptors for an individual compared to an index allele, in the order
+/// of the receptors
+#[derive(Debug)]
+pub struct ReceptorResult {
+    pub index: ClassI,
+    pub id: String,
+    pub scores: Vec<Option<f32>>,
+    pub ligand_table: String,
+}
+
+/// Scores every configured receptor between each index allele and the individuals' genotypes
+pub fn calculate_receptor_scores(
+    indexes: &[ClassI],
+    cohort: &[Individual],
+    receptors: &[&dyn ReceptorInteraction],
+    ligand_map: &KirLigandMap,
+    ligand_policy: AmbiguityPolicy,
+) -> Vec<ReceptorResult> {
+    let motif = |allele: &ClassI| {
+        ligand_map
+            .lookup(allele)
+            .resolve(ligand_policy)
+            .info()
+            .map(|info| info.motif().clone())
+    };
+    let index_motifs = indexes
+        .iter()
+        .map(motif)
+        .collect::<Vec<Option<LigandMotif>>>();
+
+    cohort
+        .par_iter()
+        .flat_map(|individual| {
+            let genotype_motifs = individual
+                .hla_genotype
+                .iter()
+                .map(motif)
+                .collect::<Vec<Option<LigandMotif>>>();
+            let genotype = individual
+                .hla_genotype
+                .iter()
+                .zip(genotype_motifs.iter().map(Option::as_ref))
+                .collect::<Vec<HlaLigand>>();
+
+            indexes
+                .iter()
+                .zip(index_motifs.iter())
+                .map(|(index, index_motif)| ReceptorResult {
+                    index: index.clone(),
+                    id: individual.id.to_string(),
+                    scores: receptors
+                        .iter()
+                        .map(|receptor| {
+                            receptor_score(*receptor, (index, index_motif.as_ref()), &genotype)
+                        })
+                        .collect(),
+                    ligand_table: ligand_map.version().unwrap_or("NA").to_string(),
+                })
+                .collect::<Vec<ReceptorResult>>()
+        })
+        .collect()
+}
+
+/// LILRB1 and LILRB2 similarity between the index allele and an individual's genotype together
+/// with the lookups that produced them
+#[derive(Debug, Serialize)]
+pub struct LilrbResult {
+    #[serde(serialize_with = "crate::io::ser::display_serialize")]
+    pub index: ClassI,
+    pub id: String,
+    #[serde(serialize_with = "crate::io::ser::optional_float_serialize")]
+    pub lilrb1: Option<f32>,
+    #[serde(serialize_with = "crate::io::ser::optional_float_serialize")]
+    pub lilrb2: Option<f32>,
+    pub lilrb1_fallback: String,
+    pub lilrb2_fallback: String,
+    pub ligand_table: String,
+}
+
+/// LILRB scores for every index allele and individual, independent of the FS measures and peptide
+/// lengths and of whether binding predictions are available for the alleles
+pub fn calculate_lilrb_scores(
+    indexes: &[ClassI],
+    cohort: &[Individual],
+    lilrb_receptors: &[LilrbReceptor],
+    ligand_table: &str,
+) -> Vec<LilrbResult> {
+    cohort
+        .par_iter()
+        .flat_map(|individual| {
+            indexes
+                .iter()
+                .map(|index| {
+                    let lilrb_score = |lilrb: Lilrb| {
+                        lilrb_receptors
+                            .iter()
+                            .find(|receptor| receptor.lilrb == lilrb)
+                            .and_then(|receptor| {
+                                receptor.aggregate(
+                                    individual
+                                        .hla_genotype
+                                        .iter()
+                                        .filter_map(|allele| receptor.lookup(index, allele))
+                                        .collect(),
+                                )
+                            })
+                    };
+                    let lilrb1 = lilrb_score(Lilrb::Lilrb1);
+                    let lilrb2 = lilrb_score(Lilrb::Lilrb2);
+
+                    if lilrb1.is_none() && lilrb2.is_none() {
+                        warn!(
+                            "No LILRB binding similarity scores found for index allele '{}' in individual {}",
+                            index, individual.id
+                        );
+                    }
+
+                    let fallbacks = |score: &Option<(f32, Vec<LilrbFallback>)>| match score {
+                        Some((_, fallbacks)) => fallbacks
+                            .iter()
+                            .map(|fallback| fallback.to_string())
+                            .collect::<Vec<String>>()
+                            .join(";"),
+                        None => "NA".to_string(),
+                    };
+
+                    LilrbResult {
+                        index: index.clone(),
+                        id: individual.id.to_string(),
+                        lilrb1_fallback: fallbacks(&lilrb1),
+                        lilrb2_fallback: fallbacks(&lilrb2),
+                        lilrb1: lilrb1.map(|(score, _)| score),
+                        lilrb2: lilrb2.map(|(score, _)| score),
+                        ligand_table: ligand_table.to_string(),
+                    }
+                })
+                .collect::<Vec<LilrbResult>>()
+        })
+        .collect()
+}
+
+/// Ligand entry of a receptor table, an allele (two field), an allele group or a KIR ligand motif
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ReceptorLigand {
+    Allele(String),
+    Group(String),
+    Motif(LigandMotif),
+}
+
+impl FromStr for ReceptorLigand {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        if let Ok(motif) = s.parse::<LigandMotif>() {
+            return Ok(ReceptorLigand::Motif(motif));
+        }
+
+        match s.trim_start_matches("HLA-").parse::<ClassI>() {
+            Ok(allele) if allele.to_string().contains(':') => {
+                Ok(ReceptorLigand::Allele(two_field(&allele)))
+            }
+            Ok(allele) => Ok(ReceptorLigand::Group(allele.allele_group())),
+            Err(_) => Err(Error::InvalidReceptorLigand(s.to_string())),
+        }
+    }
+}
+
+/// Engagement weights of a single receptor, allele entries take precedence over allele groups and
+/// allele groups over ligand motifs
+#[derive(Debug, Default)]
+pub struct ReceptorTable {
+    pub receptor: String,
+    pub weights: HashMap<ReceptorLigand, f32>,
+}
+
+impl ReceptorTable {
+    pub fn new(receptor: &str) -> Self {
+        Self {
+            receptor: receptor.to_string(),
+            weights: HashMap::new(),
+        }
+    }
+
+    /// Engagement tables for every KIR of the motif binding table, weighted by the binding strengths
+    pub fn from_kir_binding(kir_binding: &KirBindingTable) -> Vec<Self> {
+        let mut tables = kir_binding
+            .strengths
+            .iter()
+            .fold(
+                HashMap::<String, ReceptorTable>::new(),
+                |mut tables, ((kir, motif), strength)| {
+                    let receptor = kir.to_string();
+                    tables
+                        .entry(receptor.clone())
+                        .or_insert_with(|| ReceptorTable::new(&receptor))
+                        .weights
+                        .insert(ReceptorLigand::Motif(motif.clone()), *strength);
+                    tables
+                },
+            )
+            .into_values()
+            .collect::<Vec<ReceptorTable>>();
+        tables.sort_by(|a, b| a.receptor.cmp(&b.receptor));
+
+        tables
+    }
+}
+
+impl ReceptorInteraction for ReceptorTable {
+    fn receptor(&self) -> &str {
+        &self.receptor
+    }
+
+    fn engagement(&self, (allele, motif): HlaLigand) -> Option<f32> {
+        let allele_key = ReceptorLigand::Allele(two_field(allele));
+        let group_key = ReceptorLigand::Group(allele.allele_group());
+
+        self.weights
+            .get(&allele_key)
+            .or_else(|| self.weights.get(&group_key))
+            .or_else(|| {
+                motif.and_then(|motif| self.weights.get(&ReceptorLigand::Motif(motif.clone())))
+            })
+            .copied()
+    }
+}
+
+/// KIR receptor of the KIR-aware FS together with the table it engages HLA alleles through
+#[derive(Debug)]
+pub struct KirReceptor {
+    pub kir: Kir,
+    pub interaction: Box<dyn ReceptorInteraction>,
+}
+
+impl KirReceptor {
+    /// Receptors of every KIR in the motif binding table
+    pub fn from_kir_binding(kir_binding: &KirBindingTable) -> Vec<Self> {
+        ReceptorTable::from_kir_binding(kir_binding)
+            .into_iter()
+            .filter_map(|table| {
+                Some(Self {
+                    kir: table.receptor.parse::<Kir>().ok()?,
+                    interaction: Box::new(table),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Pairwise KIR ligand scores, lower scores are closer ligands so the lowest score is kept. Alleles
+/// identical to the index allele are not scored, like the LILRB scores
+impl ReceptorInteraction for KirLigandScores {
+    fn receptor(&self) -> &str {
+        "kir_ligand_score"
+    }
+
+    fn score(&self, index: HlaLigand, allele: HlaLigand) -> Option<f32> {
+        if index.0 == allele.0 {
+            return None;
+        }
+
+        KirLigandScores::score(self, index, allele)
+    }
+
+    fn combine(&self, scores: Vec<f32>) -> Option<f32> {
+        scores
+            .into_iter()
+            .min_by(|a, b| a.partial_cmp(b).expect("Tried to compare a NaN"))
+    }
+}
+
+/// The LILRB receptors scored in the LILRB similarity table
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Lilrb {
+    Lilrb1,
+    Lilrb2,
+}
+
+/// How the scores of the genotype alleles are combined, `index-excluded` keeps the highest score
+/// while ignoring alleles identical to the index allele
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LilrbAggregation {
+    Max,
+    Mean,
+    Sum,
+    IndexExcluded,
+}
+
+impl FromStr for LilrbAggregation {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "max" => Ok(LilrbAggregation::Max),
+            "mean" => Ok(LilrbAggregation::Mean),
+            "sum" => Ok(LilrbAggregation::Sum),
+            "index-excluded" => Ok(LilrbAggregation::IndexExcluded),
+            s => Err(Error::UnknownLilrbOption(s.to_string())),
+        }
+    }
+}
+
+/// Lookup used when an allele pair is not in the LILRB table, `exact` does not fall back
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub enum LilrbFallback {
+    Exact,
+    AlleleGroup,
+    Nearest,
+}
+
+impl FromStr for LilrbFallback {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "exact" => Ok(LilrbFallback::Exact),
+            "allele-group" => Ok(LilrbFallback::AlleleGroup),
+            "nearest" => Ok(LilrbFallback::Nearest),
+            s => Err(Error::UnknownLilrbOption(s.to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for LilrbFallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            LilrbFallback::Exact => "exact",
+            LilrbFallback::AlleleGroup => "allele-group",
+            LilrbFallback::Nearest => "nearest",
+        };
+
+        write!(f, "{}", s)
+    }
+}
+
+/// LILRB scoring options, `nearest` maps alleles to their NetMHCpan nearest neighbour
+#[derive(Debug)]
+pub struct LilrbOptions {
+    pub aggregation: LilrbAggregation,
+    pub fallback: LilrbFallback,
+    pub nearest: HashMap<String, ClassI>,
+}
+
+impl Default for LilrbOptions {
+    fn default() -> Self {
+        Self {
+            aggregation: LilrbAggregation::IndexExcluded,
+            fallback: LilrbFallback::AlleleGroup,
+            nearest: HashMap::new(),
+        }
+    }
+}
+
+/// Pairwise LILRB binding similarity of a single LILRB receptor
+#[derive(Debug)]
+pub struct LilrbReceptor {
+    pub lilrb: Lilrb,
+    pub scores: std::sync::Arc<LilrbScores>,
+    pub options: std::sync::Arc<LilrbOptions>,
+}
+
+impl LilrbReceptor {
+    /// Receptors for both LILRB1 and LILRB2 sharing the similarity table
+    pub fn from_scores(scores: LilrbScores, options: LilrbOptions) -> Vec<Self> {
+        let scores = std::sync::Arc::new(scores);
+        let options = std::sync::Arc::new(options);
+
+        vec![
+            LilrbReceptor {
+                lilrb: Lilrb::Lilrb1,
+                scores: scores.clone(),
+                options: options.clone(),
+            },
+            LilrbReceptor {
+                lilrb: Lilrb::Lilrb2,
+                scores,
+                options,
+            },
+        ]
+    }
+
+    fn value(&self, score: &LilrbScore) -> f32 {
+        match self.lilrb {
+            Lilrb::Lilrb1 => score.lilrb1_score,
+            Lilrb::Lilrb2 => score.lilrb2_score,
+        }
+    }
+
+    fn mean(&self, scores: &[&LilrbScore]) -> Option<f32> {
+        match scores.len() {
+            0 => None,
+            n => Some(scores.iter().map(|score| self.value(score)).sum::<f32>() / n as f32),
+        }
+    }
+
+    /// Similarity of the alleles and the lookup that produced it. Alleles identical to the index
+    /// allele are only scored when the table lists the pair, they are never looked up through a
+    /// fallback.
+    pub fn lookup(&self, index: &ClassI, allele: &ClassI) -> Option<(f32, LilrbFallback)> {
+        let options = &self.options;
+
+        if index == allele && options.aggregation == LilrbAggregation::IndexExcluded {
+            return None;
+        }
+
+        if let Some(score) = self.mean(&self.scores.exact(index, allele)) {
+            return Some((score, LilrbFallback::Exact));
+        }
+
+        if index == allele {
+            return None;
+        }
+
+        let score = match options.fallback {
+            LilrbFallback::AlleleGroup => self.mean(&self.scores.matching(index, allele)),
+            LilrbFallback::Nearest => {
+                let nearest = |allele: &ClassI| {
+                    options
+                        .nearest
+                        .get(&allele.to_string())
+                        .cloned()
+                        .unwrap_or_else(|| allele.clone())
+                };
+                self.mean(&self.scores.exact(&nearest(index), &nearest(allele)))
+            }
+            _ => None,
+        };
+
+        score.map(|score| (score, options.fallback))
+    }
+
+    /// Combines the scores of the genotype alleles, listing the lookups of the scores used
+    pub fn aggregate(
+        &self,
+        scores: Vec<(f32, LilrbFallback)>,
+    ) -> Option<(f32, Vec<LilrbFallback>)> {
+        let n = scores.len();
+
+        match self.options.aggregation {
+            _ if n == 0 => None,
+            LilrbAggregation::Max | LilrbAggregation::IndexExcluded => scores
+                .into_iter()
+                .max_by(|a, b| a.0.partial_cmp(&b.0).expect("Tried to compare a NaN"))
+                .map(|(score, fallback)| (score, vec![fallback])),
+            aggregation => {
+                let mut fallbacks = scores
+                    .iter()
+                    .map(|(_, fallback)| *fallback)
+                    .collect::<Vec<_>>();
+                fallbacks.sort();
+                fallbacks.dedup();
+
+                let sum = scores.iter().map(|(score, _)| score).sum::<f32>();
+                match aggregation {
+                    LilrbAggregation::Mean => Some((sum / n as f32, fallbacks)),
+                    _ => Some((sum, fallbacks)),
+                }
+            }
+        }
+    }
+}
+
+impl ReceptorInteraction for LilrbReceptor {
+    fn receptor(&self) -> &str {
+        match self.lilrb {
+            Lilrb::Lilrb1 => "lilrb1",
+            Lilrb::Lilrb2 => "lilrb2",
+        }
+    }
+
+    fn score(&self, (index, _): HlaLigand, (allele, _): HlaLigand) -> Option<f32> {
+        self.lookup(index, allele).map(|(score, _)| score)
+    }
+
+    fn combine(&self, scores: Vec<f32>) -> Option<f32> {
+        self.aggregate(
+            scores
+                .into_iter()
+                .map(|score| (score, LilrbFallback::Exact))
+                .collect(),
+        )
+        .map(|(score, _)| score)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::reader::{parse_receptor_tables, read_kir_motif_binding, read_lilrb_scores};
+
+    #[test]
+    fn test_receptor_table() {
+        let table = "# receptor\tligand\tweight\nKIR3DL2\tA3\t0.5\nKIR3DL2\tA*11:01\t1.0\nNKG2A\tB*07\t0.8\n";
+        let receptors = parse_receptor_tables(table).unwrap();
+        assert_eq!(receptors.len(), 2);
+
+        let kir3dl2 = &receptors[0];
+        let a1101 = "A*11:01:01".parse::<ClassI>().unwrap();
+        let a0301 = "A*03:01".parse::<ClassI>().unwrap();
+        let b0702 = "B*07:02".parse::<ClassI>().unwrap();
+
+        assert_eq!(kir3dl2.receptor(), "KIR3DL2");
+        assert_eq!(
+            kir3dl2.engagement((&a1101, Some(&LigandMotif::A11))),
+            Some(1.0)
+        );
+        assert_eq!(
+            kir3dl2.engagement((&a0301, Some(&LigandMotif::A3))),
+            Some(0.5)
+        );
+        assert_eq!(
+            receptor_score(
+                kir3dl2,
+                (&a1101, Some(&LigandMotif::A11)),
+                &[(&a0301, Some(&LigandMotif::A3)), (&b0702, None)]
+            ),
+            Some(0.5)
+        );
+        assert_eq!(receptors[1].engagement((&b0702, None)), Some(0.8));
+        assert!(parse_receptor_tables("NKG2A\tB*07\tstrong").is_err());
+        assert!(parse_receptor_tables("NKG2A\tB*07\tNaN").is_err());
+        assert!(parse_receptor_tables("NKG2A\tB*07\tinf").is_err());
+    }
+
+    #[test]
+    fn test_kir_binding_receptors() {
+        let receptors = ReceptorTable::from_kir_binding(&read_kir_motif_binding());
+        let kir2dl1 = receptors
+            .iter()
+            .find(|receptor| receptor.receptor() == "KIR2DL1")
+            .unwrap();
+        let c2 = "C*02:02".parse::<ClassI>().unwrap();
+
+        assert_eq!(kir2dl1.engagement((&c2, Some(&LigandMotif::C2))), Some(1.0));
+        assert_eq!(kir2dl1.engagement((&c2, Some(&LigandMotif::C1))), None);
+    }
+
+    #[test]
+    fn test_kir_ligand_receptor() {
+        use crate::io::reader::read_kir_ligand_scores;
+        use crate::test_utils::cohort;
+
+        let index = "C*06:02".parse::<ClassI>().unwrap();
+        let kir_ligand_scores = read_kir_ligand_scores();
+        let input = "ID,A1,A2,B1,B2,C1,C2,KIR2DL1,KIR2DS1\n\
+                     with_index,A*01:01,A*02:01,B*57:01,B*08:01,C*06:02,C*01:02,1,0\n\
+                     c2,A*01:01,A*02:01,B*57:01,B*08:01,C*04:01,C*01:02,1,0\n";
+        let cohort = cohort(input);
+        let results = calculate_receptor_scores(
+            &[index],
+            &cohort,
+            &[&kir_ligand_scores],
+            &KirLigandMap::init().unwrap(),
+            AmbiguityPolicy::First,
+        );
+
+        // A single score per individual and index allele, the index allele itself is not scored
+        assert_eq!(results.len(), 2);
+        let score = |id: &str| {
+            let result = results.iter().find(|result| result.id == id).unwrap();
+            result.scores.clone()
+        };
+        assert_eq!(kir_ligand_scores.receptor(), "kir_ligand_score");
+        assert_eq!(score("with_index"), vec![Some(1.0)]);
+        assert_eq!(score("c2"), vec![Some(0.0)]);
+    }
+
+    #[test]
+    fn test_lilrb_receptors() {
+        let receptors = LilrbReceptor::from_scores(read_lilrb_scores(), LilrbOptions::default());
+        let a0101 = "A*01:01".parse::<ClassI>().unwrap();
+        let a0201 = "A*02:01".parse::<ClassI>().unwrap();
+
+        assert_eq!(
+            receptors[0].score((&a0101, None), (&a0201, None)),
+            Some(0.58)
+        );
+        assert_eq!(
+            receptors[1].score((&a0101, None), (&a0201, None)),
+            Some(0.94)
+        );
+        assert_eq!(receptors[0].score((&a0101, None), (&a0101, None)), None);
+    }
+
+    #[test]
+    fn test_lilrb_options() {
+        let a0101 = "A*01:01".parse::<ClassI>().unwrap();
+        let a0201 = "A*02:01".parse::<ClassI>().unwrap();
+        let a0302 = "A*03:02".parse::<ClassI>().unwrap();
+        let a0301 = "A*03:01".parse::<ClassI>().unwrap();
+        let options = |aggregation, fallback| LilrbOptions {
+            aggregation,
+            fallback,
+            nearest: vec![(a0302.to_string(), a0301.clone())]
+                .into_iter()
+                .collect(),
+        };
+
+        let exact = LilrbReceptor::from_scores(
+            read_lilrb_scores(),
+            options(LilrbAggregation::Max, LilrbFallback::Exact),
+        );
+        assert_eq!(exact[0].lookup(&a0101, &a0302), None);
+        // The table has no A*01:01 pair with itself
+        assert_eq!(exact[0].lookup(&a0101, &a0101), None);
+
+        let nearest = LilrbReceptor::from_scores(
+            read_lilrb_scores(),
+            options(LilrbAggregation::Mean, LilrbFallback::Nearest),
+        );
+        assert_eq!(
+            nearest[0].lookup(&a0101, &a0302),
+            Some((0.92, LilrbFallback::Nearest))
+        );
+        assert_eq!(
+            nearest[0].aggregate(vec![
+                (0.5, LilrbFallback::Exact),
+                (1.0, LilrbFallback::Nearest)
+            ]),
+            Some((0.75, vec![LilrbFallback::Exact, LilrbFallback::Nearest]))
+        );
+
+        // A missing pair of the index allele with itself is not looked up by allele group
+        let allele_group = LilrbReceptor::from_scores(
+            read_lilrb_scores(),
+            options(LilrbAggregation::Max, LilrbFallback::AlleleGroup),
+        );
+        assert_eq!(
+            allele_group[0].lookup(&a0101, &a0201),
+            Some((0.58, LilrbFallback::Exact))
+        );
+        assert_eq!(allele_group[0].lookup(&a0101, &a0101), None);
+        assert!("none".parse::<LilrbFallback>().is_err());
+    }
+}
@@ -0,0 +1,447 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::error::Error;
+
+use log::info;
+use serde::{Deserialize, Serialize};
+
+/// Suffix of the file describing the run of a shard, used to find the shard outputs and prefix
+pub const SHARD_INFO_FILE: &str = "shard_info.csv";
+/// Outputs holding the allele pairs of a shard, concatenated when merging
+const PAIR_OUTPUTS: [&str; 1] = ["allele_fs_result.csv"];
+/// Outputs holding every allele of the run, identical across shards
+const ALLELE_OUTPUTS: [&str; 3] = [
+    "allele_binding_summary.csv",
+    "allele_metadata.csv",
+    "ambiguous_ligands.csv",
+];
+
+/// Part `index` (1-based) of `count` parts of the allele pairs
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Shard {
+    pub index: usize,
+    pub count: usize,
+}
+
+impl FromStr for Shard {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidShard(s.to_string());
+        let (index, count) = s.split_once('/').ok_or_else(invalid)?;
+        let index = index.trim().parse::<usize>().map_err(|_| invalid())?;
+        let count = count.trim().parse::<usize>().map_err(|_| invalid())?;
+
+        if index == 0 || index > count {
+            return Err(invalid());
+        }
+
+        Ok(Self { index, count })
+    }
+}
+
+impl Shard {
+    /// Items of the shard, every `count`-th item starting at the shard index. The allele pairs have
+    /// to be generated in the same order in every shard (e.g. from sorted alleles).
+    pub fn select<I>(self, items: I) -> impl Iterator<Item = I::Item>
+    where
+        I: Iterator,
+    {
+        items
+            .enumerate()
+            .filter(move |(i, _)| i % self.count == self.index - 1)
+            .map(|(_, item)| item)
+    }
+}
+
+/// Parameters and inputs of a shard run, all fields but the shard and its number of pairs have to
+/// match across the shards being merged
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ShardInfo {
+    pub shard: usize,
+    pub shards: usize,
+    pub pairs: usize,
+    pub alleles: usize,
+    pub binding_predictions: String,
+    pub measures: String,
+    pub peptide_lengths: String,
+    pub threshold: String,
+    pub allele_thresholds: String,
+    pub binding_weight: String,
+    pub bootstrap: String,
+    pub null_model: String,
+    pub unique: bool,
+    pub ligand_table: String,
+    pub ligand_ambiguity: String,
+    pub overlap_metrics: String,
+    /// The shards were run without writing the allele FS results
+    pub skip_allele_fs: bool,
+}
+
+impl ShardInfo {
+    fn run_parameters(&self) -> ShardInfo {
+        ShardInfo {
+            shard: 0,
+            pairs: 0,
+            ..self.clone()
+        }
+    }
+}
+
+/// File names and sizes of input files, to check shards were run on the same inputs without
+/// requiring the same paths
+pub fn input_files(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let size = std::fs::metadata(path).map(|meta| meta.len()).unwrap_or(0);
+            format!("{}:{}", name, size)
+        })
+        .collect::<Vec<String>>()
+        .join(";")
+}
+
+/// Outputs of a single shard, found through its shard information file
+#[derive(Debug)]
+struct ShardOutput {
+    dir: PathBuf,
+    prefix: String,
+    info: ShardInfo,
+}
+
+impl ShardOutput {
+    fn open(dir: &Path) -> Result<Self, Error> {
+        let missing = || Error::MissingShardInfo(dir.to_path_buf());
+
+        let prefix = std::fs::read_dir(dir)
+            .map_err(|_| missing())?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .find_map(|name| name.strip_suffix(SHARD_INFO_FILE).map(str::to_string))
+            .ok_or_else(missing)?;
+
+        let info = csv::ReaderBuilder::new()
+            .delimiter(crate::DEFAULT_DELIM)
+            .from_path(dir.join(format!("{}{}", prefix, SHARD_INFO_FILE)))?
+            .deserialize::<ShardInfo>()
+            .next()
+            .ok_or_else(missing)??;
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            prefix,
+            info,
+        })
+    }
+
+    fn output(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}{}", self.prefix, name))
+    }
+}
+
+/// Header and records of a CSV output
+fn read_records(path: &Path) -> Result<(csv::StringRecord, Vec<csv::StringRecord>), Error> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .delimiter(crate::DEFAULT_DELIM)
+        .from_path(path)?;
+    let header = rdr.headers()?.clone();
+    let records = rdr.records().collect::<Result<Vec<_>, _>>()?;
+
+    Ok((header, records))
+}
+
+/// Checks the shards were run with the same parameters and inputs and together cover every shard
+/// and allele pair exactly once
+fn check_shards(shards: &[ShardOutput]) -> Result<(), Error> {
+    let first = &shards
+        .first()
+        .ok_or_else(|| Error::InconsistentShards("no shards given".to_string()))?
+        .info;
+
+    if let Some(shard) = shards
+        .iter()
+        .find(|shard| shard.info.run_parameters() != first.run_parameters())
+    {
+        return Err(Error::InconsistentShards(format!(
+            "'{}' was run with different parameters or inputs than shard {}",
+            shard.dir.display(),
+            first.shard
+        )));
+    }
+
+    let mut indexes = shards
+        .iter()
+        .map(|shard| shard.info.shard)
+        .collect::<Vec<usize>>();
+    indexes.sort_unstable();
+    if indexes != (1..=first.shards).collect::<Vec<usize>>() {
+        return Err(Error::InconsistentShards(format!(
+            "expected shards 1 to {} exactly once, got {:?}",
+            first.shards, indexes
+        )));
+    }
+
+    let pairs = shards.iter().map(|shard| shard.info.pairs).sum::<usize>();
+    let expected = first.alleles * first.alleles.saturating_sub(1);
+    if pairs != expected {
+        return Err(Error::InconsistentShards(format!(
+            "shards cover {} allele pairs instead of {}",
+            pairs, expected
+        )));
+    }
+
+    Ok(())
+}
+
+/// Merges the outputs of all shards of a run into a single output directory
+pub fn merge_shards(shard_dirs: &[PathBuf], output: &Path, prefix: &str) -> Result<(), Error> {
+    let shards = shard_dirs
+        .iter()
+        .map(|dir| ShardOutput::open(dir))
+        .collect::<Result<Vec<ShardOutput>, Error>>()?;
+    check_shards(&shards)?;
+
+    if !output.exists() {
+        std::fs::create_dir_all(output).map_err(|_| Error::CouldNotCreateOutputDir)?
+    }
+
+    let writer = |name: &str| {
+        csv::WriterBuilder::new()
+            .delimiter(crate::DEFAULT_DELIM)
+            .from_path(output.join(format!("{}{}", prefix, name)))
+            .map_err(|_| Error::CouldNotCreateOutputFile)
+    };
+
+    let pair_outputs = if shards[0].info.skip_allele_fs {
+        info!("The shards were run with --skip-allele-fs, not merging the allele FS results");
+        &PAIR_OUTPUTS[..0]
+    } else {
+        &PAIR_OUTPUTS[..]
+    };
+
+    for name in pair_outputs.iter() {
+        let outputs = shards
+            .iter()
+            .map(|shard| shard.output(name))
+            .filter(|path| path.exists())
+            .collect::<Vec<PathBuf>>();
+        if outputs.len() != shards.len() {
+            return Err(Error::InconsistentShards(format!(
+                "'{}' is missing from some of the shards",
+                name
+            )));
+        }
+
+        info!("Merging '{}' of {} shards", name, outputs.len());
+        let merge_error = |_| Error::CouldNotMergeShards(name.to_string());
+        let mut wtr = writer(name)?;
+        let mut merged_header = None;
+        for path in outputs {
+            let mut rdr = csv::ReaderBuilder::new()
+                .delimiter(crate::DEFAULT_DELIM)
+                .from_path(&path)?;
+            let header = rdr.headers()?.clone();
+
+            // Shards without any pairs have no header
+            if header.is_empty() {
+                continue;
+            }
+            match &merged_header {
+                None => {
+                    wtr.write_record(&header).map_err(merge_error)?;
+                    merged_header = Some(header);
+                }
+                Some(merged_header) if merged_header != &header => {
+                    return Err(Error::InconsistentShards(format!(
+                        "'{}' has different columns",
+                        path.display()
+                    )));
+                }
+                _ => (),
+            }
+
+            for record in rdr.records() {
+                wtr.write_record(&record?).map_err(merge_error)?;
+            }
+        }
+        wtr.flush()
+            .map_err(|_| Error::CouldNotMergeShards(name.to_string()))?;
+    }
+
+    for name in ALLELE_OUTPUTS.iter() {
+        let mut outputs = shards
+            .iter()
+            .map(|shard| read_records(&shard.output(name)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        outputs.iter_mut().for_each(|(_, records)| {
+            records.sort_by(|a, b| a.iter().cmp(b.iter()));
+        });
+
+        let (header, records) = &outputs[0];
+        if outputs.iter().any(|output| output != &outputs[0]) {
+            return Err(Error::InconsistentShards(format!(
+                "'{}' differs between shards",
+                name
+            )));
+        }
+
+        // Outputs without any entries have no header
+        let mut wtr = writer(name)?;
+        std::iter::once(header)
+            .filter(|header| !header.is_empty())
+            .chain(records.iter())
+            .try_for_each(|record| wtr.write_record(record))
+            .and_then(|_| wtr.flush().map_err(csv::Error::from))
+            .map_err(|_| Error::CouldNotMergeShards(name.to_string()))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shard_info(shard: usize, pairs: usize) -> ShardInfo {
+        ShardInfo {
+            shard,
+            shards: 2,
+            pairs,
+            alleles: 3,
+            binding_predictions: "netmhcpan_wBA.txt:1024".to_string(),
+            measures: "TCR:2,3,4,5,6,9;KIR:2,7,8,9".to_string(),
+            peptide_lengths: "9".to_string(),
+            threshold: "2".to_string(),
+            allele_thresholds: String::new(),
+            binding_weight: "hard".to_string(),
+            bootstrap: "NA".to_string(),
+            null_model: "NA".to_string(),
+            unique: false,
+            ligand_table: "bundled".to_string(),
+            ligand_ambiguity: "first".to_string(),
+            overlap_metrics: String::new(),
+            skip_allele_fs: false,
+        }
+    }
+
+    fn write_shard(dir: &Path, info: &ShardInfo, fs_rows: &[&str]) {
+        let mut wtr = csv::Writer::from_path(dir.join(format!("s_{}", SHARD_INFO_FILE))).unwrap();
+        wtr.serialize(info).unwrap();
+        wtr.flush().unwrap();
+
+        let fs_result = std::iter::once("measure,index,non_index")
+            .chain(fs_rows.iter().copied())
+            .collect::<Vec<&str>>()
+            .join("\n");
+        std::fs::write(dir.join("s_allele_fs_result.csv"), fs_result).unwrap();
+        ALLELE_OUTPUTS.iter().for_each(|name| {
+            std::fs::write(
+                dir.join(format!("s_{}", name)),
+                "allele\nA*01:01\nB*27:05\n",
+            )
+            .unwrap()
+        });
+    }
+
+    #[test]
+    fn test_parse_shard() {
+        assert_eq!(
+            "2/4".parse::<Shard>().unwrap(),
+            Shard { index: 2, count: 4 }
+        );
+        assert!("0/4".parse::<Shard>().is_err());
+        assert!("5/4".parse::<Shard>().is_err());
+        assert!("1".parse::<Shard>().is_err());
+    }
+
+    #[test]
+    fn test_shard_select() {
+        let shards = (1..=3)
+            .map(|index| {
+                Shard { index, count: 3 }
+                    .select(0..10)
+                    .collect::<Vec<usize>>()
+            })
+            .collect::<Vec<Vec<usize>>>();
+
+        assert_eq!(shards[0], vec![0, 3, 6, 9]);
+        assert_eq!(shards.iter().map(|shard| shard.len()).sum::<usize>(), 10);
+    }
+
+    #[test]
+    fn test_merge_shards() {
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        write_shard(
+            first.path(),
+            &shard_info(1, 3),
+            &["KIR,A,B", "KIR,A,C", "KIR,B,A"],
+        );
+        write_shard(
+            second.path(),
+            &shard_info(2, 3),
+            &["KIR,B,C", "KIR,C,A", "KIR,C,B"],
+        );
+        let dirs = vec![first.path().to_path_buf(), second.path().to_path_buf()];
+
+        merge_shards(&dirs, output.path(), "").unwrap();
+        let (_, records) = read_records(&output.path().join("allele_fs_result.csv")).unwrap();
+        assert_eq!(records.len(), 6);
+
+        let mut other_run = shard_info(2, 3);
+        other_run.unique = true;
+        write_shard(
+            second.path(),
+            &other_run,
+            &["KIR,B,C", "KIR,C,A", "KIR,C,B"],
+        );
+        assert!(merge_shards(&dirs, output.path(), "").is_err());
+
+        write_shard(
+            second.path(),
+            &shard_info(1, 3),
+            &["KIR,B,C", "KIR,C,A", "KIR,C,B"],
+        );
+        assert!(merge_shards(&dirs, output.path(), "").is_err());
+
+        let mut other_run = shard_info(2, 3);
+        other_run.ligand_ambiguity = "skip".to_string();
+        write_shard(
+            second.path(),
+            &other_run,
+            &["KIR,B,C", "KIR,C,A", "KIR,C,B"],
+        );
+        assert!(merge_shards(&dirs, output.path(), "").is_err());
+    }
+
+    #[test]
+    fn test_merge_shards_without_allele_fs() {
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        let dirs = vec![first.path().to_path_buf(), second.path().to_path_buf()];
+        let remove_allele_fs = || {
+            dirs.iter()
+                .for_each(|dir| std::fs::remove_file(dir.join("s_allele_fs_result.csv")).unwrap())
+        };
+
+        write_shard(first.path(), &shard_info(1, 3), &[]);
+        write_shard(second.path(), &shard_info(2, 3), &[]);
+        remove_allele_fs();
+        let output = tempfile::tempdir().unwrap();
+        assert!(merge_shards(&dirs, output.path(), "").is_err());
+
+        let skipped = |shard| ShardInfo {
+            skip_allele_fs: true,
+            ..shard_info(shard, 3)
+        };
+        write_shard(first.path(), &skipped(1), &[]);
+        write_shard(second.path(), &skipped(2), &[]);
+        remove_allele_fs();
+        merge_shards(&dirs, output.path(), "").unwrap();
+        assert!(!output.path().join("allele_fs_result.csv").exists());
+        assert!(output.path().join(ALLELE_OUTPUTS[0]).exists());
+    }
+}
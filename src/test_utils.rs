@@ -0,0 +1,46 @@
+use crate::calc::{BindingCutoff, BindingWeight, HashedMotifs, KirReference, Measure, MotifSets};
+use crate::cohort::{CohortTemp, Individual};
+use crate::io::reader::{read_kir_allotypes, read_kir_ligand_scores, read_kir_motif_binding};
+
+use netmhcpan::reader::read_raw_netmhcpan;
+use netmhcpan::result::BindingData;
+
+const TEST_BINDING_PREDICTIONS: &str = "tests/input/binding_predictions/netmhcpan_wBA.txt";
+
+/// Binding predictions of the test input, holding B*27:05 and A*03:01
+pub fn binding_data() -> BindingData {
+    read_raw_netmhcpan(vec![TEST_BINDING_PREDICTIONS]).unwrap()
+}
+
+/// Motif sets of every allele in the test binding predictions for the KIR measure and 9mers at the
+/// weak binding rank threshold
+pub fn motif_sets(weight: BindingWeight, unique: bool) -> MotifSets {
+    let binding_data = binding_data();
+    let measures = vec![crate::KIR_DEF.parse::<Measure>().unwrap()];
+    let all_alleles = binding_data.list_alleles().into_iter().collect();
+
+    HashedMotifs::new(&binding_data, &all_alleles, &measures, &[9]).motif_sets(
+        (
+            &BindingCutoff::Rank(binding_data.weak_threshold()).into(),
+            weight,
+        ),
+        unique,
+    )
+}
+
+/// KIR reference built from the bundled KIR tables
+pub fn kir_reference() -> KirReference {
+    KirReference::new(
+        read_kir_motif_binding(),
+        read_kir_allotypes(),
+        read_kir_ligand_scores(),
+    )
+}
+
+/// Individuals of a cohort given in the cohort CSV format
+pub fn cohort(input: &str) -> Vec<Individual> {
+    csv::Reader::from_reader(input.as_bytes())
+        .deserialize::<CohortTemp>()
+        .map(|temp| Individual::from(temp.unwrap()))
+        .collect()
+}